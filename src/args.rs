@@ -1,5 +1,8 @@
 use clap::Parser;
 
+use crate::throttle::parse_rate;
+use crate::tls::TlsBackend;
+
 /// Download an artifact from a given url and optionally verify checksum.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -23,6 +26,38 @@ pub struct Args {
     #[arg(short='A', long="user-agent")]
     pub user_agent: Option<String>,
 
+    /// Server user and password (prompts for the password if omitted)
+    #[arg(short='u', long, value_name="USER[:PASSWORD]")]
+    pub user: Option<String>,
+
+    /// Use HTTP Basic Authentication (default)
+    #[arg(long, conflicts_with="digest")]
+    pub basic: bool,
+
+    /// Use HTTP Digest Authentication
+    #[arg(long)]
+    pub digest: bool,
+
+    /// OAuth 2 Bearer Token
+    #[arg(long="oauth2-bearer", value_name="TOKEN", conflicts_with="user")]
+    pub oauth2_bearer: Option<String>,
+
+    /// Read credentials from ~/.netrc
+    #[arg(short='n', long)]
+    pub netrc: bool,
+
+    /// Read credentials from ~/.netrc, if it exists
+    #[arg(long="netrc-optional")]
+    pub netrc_optional: bool,
+
+    /// Read credentials from FILE instead of ~/.netrc
+    #[arg(long="netrc-file", value_name="FILE")]
+    pub netrc_file: Option<String>,
+
+    /// Send credentials to other hosts when following redirects
+    #[arg(long="location-trusted")]
+    pub location_trusted: bool,
+
     /// Post data.
     #[arg(short, long)]
     pub data: Option<String>,
@@ -43,22 +78,86 @@ pub struct Args {
     #[arg(short='L', long)]
     pub location: bool,
 
-    /// Maximum number of redirects allowed.
-    #[arg(long, default_value_t=5)]
-    pub max_redirs: usize,
+    /// Maximum number of redirects allowed (-1 for unlimited).
+    #[arg(long, default_value_t=5, allow_negative_numbers=true, value_parser=clap::value_parser!(i64).range(-1..))]
+    pub max_redirs: i64,
+
+    /// Allow redirects to this host only ('*.' matches subdomains, may be repeated)
+    #[arg(long="redirect-allow-host", value_name="PATTERN")]
+    pub redirect_allow_host: Vec<String>,
+
+    /// Do not switch to GET after following a 301 redirect
+    #[arg(long)]
+    pub post301: bool,
+
+    /// Do not switch to GET after following a 302 redirect
+    #[arg(long)]
+    pub post302: bool,
+
+    /// Do not switch to GET after following a 303 redirect
+    #[arg(long)]
+    pub post303: bool,
 
     /// Maximum file size to download.
     #[arg(long, default_value_t=0)]
     pub max_filesize: u64,
 
+    /// Request a compressed response and decode it.
+    #[arg(long)]
+    pub compressed: bool,
+
+    /// Verify checksums of the encoded data instead of the decoded data (see --compressed).
+    #[arg(long="checksum-encoded")]
+    pub checksum_encoded: bool,
+
     /// Maximum time allowed for connection in seconds.
     #[arg(long, default_value_t=0)]
     pub connect_timeout: u64,
 
+    /// Use ADDRESS(es) for HOST and PORT (may be repeated)
+    #[arg(long, value_name="HOST:PORT:ADDRESS[,ADDRESS]...")]
+    pub resolve: Vec<String>,
+
+    /// Connect to HOST2 and PORT2 instead of HOST1 and PORT1 (may be repeated)
+    #[arg(long="connect-to", value_name="HOST1:PORT1:HOST2:PORT2")]
+    pub connect_to: Vec<String>,
+
+    /// Resolve names to IPv4 addresses only
+    #[arg(short='4', long, conflicts_with="ipv6")]
+    pub ipv4: bool,
+
+    /// Resolve names to IPv6 addresses only
+    #[arg(short='6', long)]
+    pub ipv6: bool,
+
+    /// Connect through this Unix domain socket instead of using the network
+    #[arg(long="unix-socket", value_name="PATH")]
+    pub unix_socket: Option<String>,
+
+    /// Connect through this abstract Unix domain socket (Linux)
+    #[arg(long="abstract-unix-socket", value_name="NAME", conflicts_with="unix_socket")]
+    pub abstract_unix_socket: Option<String>,
+
     /// Maximum time allowed for transfer in seconds.
     #[arg(short, long, default_value_t=0)]
     pub max_time: u64,
 
+    /// Limit transfer speed to RATE bytes per second (K, M and G suffixes allowed).
+    #[arg(long="limit-rate", value_name="RATE", value_parser=parse_rate)]
+    pub limit_rate: Option<u64>,
+
+    /// Stop transfers slower than this (bytes per second, see --speed-time).
+    #[arg(short='Y', long="speed-limit", value_name="SPEED", value_parser=parse_rate)]
+    pub speed_limit: Option<u64>,
+
+    /// Trigger 'speed-limit' abort after this time in seconds (default: 30).
+    #[arg(short='y', long="speed-time", value_name="SECONDS")]
+    pub speed_time: Option<u64>,
+
+    /// Download using N parallel range requests (if supported by server).
+    #[arg(long, default_value_t=1, value_parser=clap::value_parser!(u16).range(1..))]
+    pub segments: u16,
+
     /// Use TLSv1.0 or later
     #[arg(short='1', long)]
     pub tlsv1 : bool,
@@ -79,10 +178,54 @@ pub struct Args {
     #[arg(long="tlsv1.3")]
     pub tlsv1_3: bool,
 
+    /// Maximum TLS version (1.0, 1.1, 1.2 or 1.3)
+    #[arg(long="tls-max", value_name="VERSION", value_parser=["1.0", "1.1", "1.2", "1.3"])]
+    pub tls_max: Option<String>,
+
+    /// TLS 1.2 cipher suites to use (colon separated list, requires rustls)
+    #[arg(long, value_name="LIST")]
+    pub ciphers: Option<String>,
+
+    /// TLS 1.3 cipher suites to use (colon separated list, requires rustls)
+    #[arg(long="tls13-ciphers", value_name="LIST")]
+    pub tls13_ciphers: Option<String>,
+
+    /// Key exchange groups to use (colon separated list, requires rustls)
+    #[arg(long, value_name="LIST")]
+    pub curves: Option<String>,
+
+    /// TLS backend used for servers (default: native, unless an option requires rustls)
+    #[arg(long="tls-backend", value_name="BACKEND")]
+    pub tls_backend: Option<TlsBackend>,
+
+    /// Use HTTP/1.1
+    #[arg(long="http1.1", conflicts_with_all=["http2", "http2_prior_knowledge", "http3", "http3_only"])]
+    pub http1_1: bool,
+
+    /// Use HTTP/2 if offered by the server (negotiated via TLS ALPN)
+    #[arg(long, conflicts_with_all=["http2_prior_knowledge", "http3", "http3_only"])]
+    pub http2: bool,
+
+    /// Use HTTP/2 without negotiation (h2c for http URLs)
+    #[arg(long="http2-prior-knowledge", conflicts_with_all=["http3", "http3_only"])]
+    pub http2_prior_knowledge: bool,
+
+    /// Use HTTP/3 for https URLs, falls back to HTTP/2 or HTTP/1.1 if the QUIC connection fails
+    #[arg(long, conflicts_with="http3_only")]
+    pub http3: bool,
+
+    /// Use HTTP/3 only
+    #[arg(long="http3-only")]
+    pub http3_only: bool,
+
     /// Enable or disable Protocols
     #[arg(long, allow_hyphen_values=true, default_value="")]
     pub proto: String,
 
+    /// Enable or disable Protocols on redirect
+    #[arg(long="proto-redir", allow_hyphen_values=true, default_value="")]
+    pub proto_redir: String,
+
     /// Silent Mode
     #[arg(short, long)]
     pub silent: bool,
@@ -95,10 +238,54 @@ pub struct Args {
     #[arg(short, long)]
     pub verbose: bool,
 
+    /// Write a hex dump of all incoming and outgoing data to FILE ('-' for stdout, '%' for stderr)
+    #[arg(long, value_name="FILE")]
+    pub trace: Option<String>,
+
+    /// Like --trace, but without hex output
+    #[arg(long="trace-ascii", value_name="FILE")]
+    pub trace_ascii: Option<String>,
+
+    /// Add time stamps to trace and verbose output
+    #[arg(long="trace-time")]
+    pub trace_time: bool,
+
+    /// Display transfer progress as a bar
+    #[arg(short='#', long="progress-bar")]
+    pub progress_bar: bool,
+
+    /// Do not show the progress meter
+    #[arg(long="no-progress-meter")]
+    pub no_progress_meter: bool,
+
+    /// Send If-None-Match with the ETag read from FILE
+    #[arg(long="etag-compare", value_name="FILE")]
+    pub etag_compare: Option<String>,
+
+    /// Save the ETag of the response to FILE
+    #[arg(long="etag-save", value_name="FILE")]
+    pub etag_save: Option<String>,
+
+    /// Transfer based on a time condition: HTTP date, ISO date (2025-01-01[T12:00:00], UTC) or file; '-' prefix for If-Unmodified-Since
+    #[arg(short='z', long="time-cond", value_name="TIME", allow_hyphen_values=true)]
+    pub time_cond: Option<String>,
+
+    /// Set the modification time of the output file from Last-Modified
+    #[arg(short='R', long="remote-time")]
+    pub remote_time: bool,
+
     /// Include HTTP response headers in the output.
     #[arg(short, long)]
     pub include: bool,
 
+    /// Write the received headers to FILE ('-' for stdout), header names in lowercase
+    #[arg(short='D', long="dump-header", value_name="FILE")]
+    pub dump_header: Option<String>,
+
+    /// Show response headers only (uses HEAD request)
+    #[arg(short='I', long)]
+    pub head: bool,
+
     /// Fail silently (no output at all) on HTTP errors
     #[arg(short, long)]
     pub fail: bool,
@@ -107,17 +294,117 @@ pub struct Args {
     #[arg(long="fail-with-body")]
     pub fail_with_body: bool,
 
-    /// [protocol://]host[:port] Use this proxy
+    /// Output FORMAT after completion ('@file' reads format from file)
+    #[arg(short='w', long="write-out", value_name="FORMAT")]
+    pub write_out: Option<String>,
+
+    /// Write a transfer report ('json' to stderr or 'json=FILE')
+    #[arg(long, value_name="json[=FILE]")]
+    pub report: Option<String>,
+
+    /// [protocol://]host[:port] Use this proxy (http, https, socks4, socks4a, socks5, socks5h)
     #[arg(short='x', long)]
     pub proxy: Option<String>,
 
-    /// CA certificate to verify peer against
-    #[arg(long)]
-    pub cacert: Option<String>,
+    /// Use a SOCKS5 proxy on HOST[:PORT] (host names are resolved locally)
+    #[arg(long, value_name="HOST[:PORT]", conflicts_with_all=["proxy", "socks5_hostname"])]
+    pub socks5: Option<String>,
+
+    /// Use a SOCKS5 proxy on HOST[:PORT] (host names are resolved by the proxy)
+    #[arg(long="socks5-hostname", value_name="HOST[:PORT]", conflicts_with="proxy")]
+    pub socks5_hostname: Option<String>,
+
+    /// Proxy user and password (prompts for the password if omitted)
+    #[arg(short='U', long="proxy-user", value_name="USER[:PASSWORD]")]
+    pub proxy_user: Option<String>,
+
+    /// Pass custom header(s) to the proxy
+    #[arg(long="proxy-header", value_name="HEADER")]
+    pub proxy_header: Vec<String>,
+
+    /// List of hosts which do not use a proxy ('*' for all hosts)
+    #[arg(long, value_name="NO-PROXY-LIST")]
+    pub noproxy: Option<String>,
 
-    /// Use this CRL list
+    /// Ignore proxy environment variables (http_proxy, https_proxy, all_proxy, no_proxy)
+    #[arg(long="ignore-proxy-env")]
+    pub ignore_proxy_env: bool,
+
+    /// CA certificate(s) to verify peer against (PEM or DER, may be repeated)
+    #[arg(long, value_name="FILE")]
+    pub cacert: Vec<String>,
+
+    /// Directories of CA certificates to verify peer against (separated by ':')
+    #[arg(long, value_name="DIR")]
+    pub capath: Option<String>,
+
+    /// Use the CA certificates of the system store (instead of the bundled ones of rustls)
+    #[arg(long="ca-native", conflicts_with="no_system_ca")]
+    pub ca_native: bool,
+
+    /// Do not trust built-in or system CA certificates (only --cacert and --capath)
+    #[arg(long="no-system-ca")]
+    pub no_system_ca: bool,
+
+    /// Client certificate file and optional password
+    #[arg(short='E', long, value_name="CERTIFICATE[:PASSWORD]")]
+    pub cert: Option<String>,
+
+    /// Certificate type (PEM, DER or P12)
+    #[arg(long="cert-type", value_name="TYPE")]
+    pub cert_type: Option<String>,
+
+    /// Private key file (if not contained in the certificate file)
     #[arg(long)]
-    pub crlfile: Option<String>,
+    pub key: Option<String>,
+
+    /// Passphrase for the private key
+    #[arg(long, value_name="PHRASE")]
+    pub pass: Option<String>,
+
+    /// Public key (PEM/DER file) or sha256//<base64> hashes (separated by ';') to verify peer against
+    #[arg(long, value_name="HASHES")]
+    pub pinnedpubkey: Option<String>,
+
+    /// Require a valid OCSP response stapled by the server (revoked or missing responses fail)
+    #[arg(long="cert-status")]
+    pub cert_status: bool,
+
+    /// Show the certificates, TLS version, cipher and ALPN protocol of the server connection (on stderr)
+    #[arg(long="show-certs")]
+    pub show_certs: bool,
+
+    /// Use this CRL list (PEM or DER, may be repeated)
+    #[arg(long, value_name="FILE")]
+    pub crlfile: Vec<String>,
+
+    /// CA certificate to verify the HTTPS proxy against
+    #[arg(long="proxy-cacert", value_name="FILE")]
+    pub proxy_cacert: Option<String>,
+
+    /// Use this CRL list for the HTTPS proxy
+    #[arg(long="proxy-crlfile", value_name="FILE")]
+    pub proxy_crlfile: Option<String>,
+
+    /// Allow insecure connections to the HTTPS proxy
+    #[arg(long="proxy-insecure")]
+    pub proxy_insecure: bool,
+
+    /// Client certificate file and optional password for the HTTPS proxy
+    #[arg(long="proxy-cert", value_name="CERTIFICATE[:PASSWORD]")]
+    pub proxy_cert: Option<String>,
+
+    /// Certificate type of --proxy-cert (PEM, DER or P12)
+    #[arg(long="proxy-cert-type", value_name="TYPE")]
+    pub proxy_cert_type: Option<String>,
+
+    /// Private key file for the HTTPS proxy
+    #[arg(long="proxy-key", value_name="KEY")]
+    pub proxy_key: Option<String>,
+
+    /// Passphrase for the private key of the HTTPS proxy
+    #[arg(long="proxy-pass", value_name="PHRASE")]
+    pub proxy_pass: Option<String>,
 
     /// SHA256 checksum of the artifact to download.
     #[arg(long)]
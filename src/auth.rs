@@ -0,0 +1,337 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use md5::Md5;
+use reqwest::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::io::{stdin, IsTerminal};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::args::Args;
+use crate::netrc::Netrc;
+
+/// Challenge of a server requesting Digest authentication.
+#[derive(Clone, Debug, PartialEq)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: String,
+    qop: Option<String>,
+}
+
+enum Auth {
+    Credentials { user: String, password: String },
+    Bearer(String),
+}
+
+struct State {
+    auth: Option<Auth>,
+    digest: bool,
+    netrc: Option<Netrc>,
+    challenge: Option<DigestChallenge>,
+    nonce_count: u32,
+}
+
+static AUTH: OnceLock<Mutex<State>> = OnceLock::new();
+
+/// Sets up authentication (-u, --basic, --digest, --oauth2-bearer, --netrc).
+/// The password is prompted on the terminal, if -u does not contain a password.
+pub fn init_auth(args: &Args) -> Result<(), String> {
+    let auth = if let Some(token) = &args.oauth2_bearer {
+        Some(Auth::Bearer(token.clone()))
+    }
+    else if let Some(user) = &args.user {
+        let (user, password) = split_user_password(user, "host")?;
+        Some(Auth::Credentials { user, password })
+    }
+    else {
+        None
+    };
+
+    let netrc = load_netrc(args)?;
+    if auth.is_none() && netrc.is_none() {
+        return Ok(());
+    }
+
+    let _ = AUTH.set(Mutex::new(State { auth, digest: args.digest, netrc, challenge: None, nonce_count: 0 }));
+    Ok(())
+}
+
+/// Loads the netrc file of --netrc-file or ~/.netrc (--netrc, --netrc-optional).
+/// A missing file is an error unless --netrc-optional is specified.
+fn load_netrc(args: &Args) -> Result<Option<Netrc>, String> {
+    let filename = match &args.netrc_file {
+        Some(filename) => Some(PathBuf::from(filename)),
+        None if args.netrc || args.netrc_optional => Netrc::default_file(),
+        None => { return Ok(None); }
+    };
+
+    match filename {
+        Some(filename) if filename.exists() || !args.netrc_optional => Netrc::from_file(&filename).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn with_state<T, F: FnOnce(&mut State) -> T>(f: F) -> Option<T> {
+    AUTH.get().map(|state| f(&mut state.lock().unwrap_or_else(|err| err.into_inner())))
+}
+
+impl State {
+    /// Returns the credentials for a host: those of -u (if allowed) or those of the netrc file.
+    fn get_credentials(&self, host: &str, allow_explicit: bool) -> Option<(String, String)> {
+        match &self.auth {
+            Some(Auth::Credentials { user, password }) if allow_explicit => {
+                return Some((user.clone(), password.clone()));
+            },
+            Some(Auth::Bearer(_)) if allow_explicit => { return None; },
+            _ => {}
+        }
+
+        self.netrc.as_ref()
+            .and_then(|netrc| netrc.get(host))
+            .map(|machine| (machine.login.clone(), machine.password.clone()))
+    }
+}
+
+/// Returns the credentials of the netrc file for a host (e.g. a proxy).
+pub fn get_netrc_credentials(host: &str) -> Option<(String, String)> {
+    with_state(|state| state.get_credentials(host, false)).flatten()
+}
+
+/// Adds the Authorization header to the request.
+/// Credentials of -u and --oauth2-bearer are only added if allow_explicit is set,
+/// credentials of the netrc file are chosen by the host of the request.
+/// Digest authentication requires a challenge of a previous response (see handle_challenge).
+pub fn add_auth(request: &mut Request, allow_explicit: bool) {
+    let host = request.url().host_str().unwrap_or_default().to_string();
+    let value = with_state(|state| {
+        if let (Some(Auth::Bearer(token)), true) = (&state.auth, allow_explicit) {
+            return Some(format!("Bearer {}", token));
+        }
+
+        let (user, password) = state.get_credentials(&host, allow_explicit)?;
+        if !state.digest {
+            return Some(format!("Basic {}", BASE64.encode(format!("{}:{}", user, password))));
+        }
+
+        let challenge = state.challenge.clone()?;
+        state.nonce_count += 1;
+        let mut uri = String::from(request.url().path());
+        if let Some(query) = request.url().query() {
+            uri.push('?');
+            uri.push_str(query);
+        }
+        Some(digest_response(&challenge, &user, &password, request.method().as_str(), &uri,
+            state.nonce_count, &create_cnonce()))
+    }).flatten();
+
+    if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+        let mut value = value;
+        value.set_sensitive(true);
+        request.headers_mut().insert(AUTHORIZATION, value);
+    }
+}
+
+/// Stores the Digest challenge of a 401 response.
+/// Returns true, if the request should be repeated with credentials.
+pub fn handle_challenge(response: &Response, had_credentials: bool, allow_explicit: bool) -> bool {
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return false;
+    }
+
+    let challenge = response.headers().get_all(WWW_AUTHENTICATE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(parse_digest_challenge);
+    let challenge = match challenge {
+        Some(challenge) => challenge,
+        None => { return false; }
+    };
+
+    let host = response.url().host_str().unwrap_or_default();
+    with_state(|state| {
+        if !state.digest || state.get_credentials(host, allow_explicit).is_none() {
+            return false;
+        }
+        // credentials were rejected, unless the server reports a stale nonce
+        let stale = state.challenge.as_ref().map(|current| current.nonce != challenge.nonce).unwrap_or(true);
+        if had_credentials && !stale {
+            return false;
+        }
+        state.challenge = Some(challenge);
+        state.nonce_count = 0;
+        true
+    }).unwrap_or(false)
+}
+
+/// Splits "user:password" (-u, -U); the password of the target ("host" or "proxy")
+/// is prompted on the terminal, if it is omitted.
+pub fn split_user_password(value: &str, target: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((user, password)) => Ok((String::from(user), String::from(password))),
+        None => Ok((String::from(value), prompt_password(value, target)?)),
+    }
+}
+
+fn prompt_password(user: &str, target: &str) -> Result<String, String> {
+    if !stdin().is_terminal() {
+        return Err(format!("no {} password specified for user '{}'", target, user));
+    }
+
+    // reads from the terminal without echo (also on Windows)
+    let prompt = format!("Enter {} password for user '{}':", target, user);
+    rpassword::prompt_password(prompt).map_err(|_| String::from("failed to read password"))
+}
+
+/// Splits the parameters of an authentication challenge, e.g. realm="x", qop="auth".
+fn parse_params(text: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| *c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+        let name: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if name.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => { if let Some(c) = chars.next() { value.push(c); } },
+                    '"' => { break; },
+                    _ => { value.push(c); }
+                }
+            }
+        }
+        else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                value.push(c);
+            }
+        }
+        params.push((name.trim().to_lowercase(), String::from(value.trim())));
+    }
+    params
+}
+
+fn parse_digest_challenge(header: &str) -> Option<DigestChallenge> {
+    let (scheme, params) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("digest") {
+        return None;
+    }
+
+    let params = parse_params(params);
+    let get = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+
+    let algorithm = get("algorithm").unwrap_or_else(|| String::from("MD5"));
+    if !matches!(algorithm.to_uppercase().as_str(), "MD5" | "MD5-SESS" | "SHA-256" | "SHA-256-SESS") {
+        return None;
+    }
+
+    // only qop=auth is supported (auth-int would require a hash of the body)
+    let qop = match get("qop") {
+        Some(qop) => {
+            if !qop.split(',').any(|value| value.trim() == "auth") {
+                return None;
+            }
+            Some(String::from("auth"))
+        },
+        None => None,
+    };
+
+    Some(DigestChallenge {
+        realm: get("realm").unwrap_or_default(),
+        nonce: get("nonce")?,
+        opaque: get("opaque"),
+        algorithm,
+        qop,
+    })
+}
+
+fn create_cnonce() -> String {
+    let mut data = [0u8; 16];
+    if getrandom::getrandom(&mut data).is_err() {
+        let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        data[..].copy_from_slice(&time.as_nanos().to_le_bytes());
+    }
+    hex::encode(data)
+}
+
+fn digest_response(challenge: &DigestChallenge, user: &str, password: &str, method: &str, uri: &str,
+        nonce_count: u32, cnonce: &str) -> String {
+    let algorithm = challenge.algorithm.to_uppercase();
+    let hash = |data: String| -> String {
+        if algorithm.starts_with("SHA-256") {
+            hex::encode(Sha256::digest(data.as_bytes()))
+        }
+        else {
+            hex::encode(Md5::digest(data.as_bytes()))
+        }
+    };
+
+    let nc = format!("{:08x}", nonce_count);
+    let mut ha1 = hash(format!("{}:{}:{}", user, challenge.realm, password));
+    if algorithm.ends_with("-SESS") {
+        ha1 = hash(format!("{}:{}:{}", ha1, challenge.nonce, cnonce));
+    }
+    let ha2 = hash(format!("{}:{}", method, uri));
+    let response = match &challenge.qop {
+        Some(qop) => hash(format!("{}:{}:{}:{}:{}:{}", ha1, challenge.nonce, nc, cnonce, qop, ha2)),
+        None => hash(format!("{}:{}:{}", ha1, challenge.nonce, ha2)),
+    };
+
+    let mut header = format!("Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+        user, challenge.realm, challenge.nonce, uri, challenge.algorithm, response);
+    if let Some(qop) = &challenge.qop {
+        header.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+    }
+    if let Some(opaque) = &challenge.opaque {
+        header.push_str(&format!(", opaque=\"{}\"", opaque));
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_digest_challenge() {
+        let challenge = parse_digest_challenge("Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", algorithm=MD5, nonce=\"7ypf\", opaque=\"FQhe\"");
+        assert_eq!(Some(DigestChallenge {
+            realm: String::from("http-auth@example.org"),
+            nonce: String::from("7ypf"),
+            opaque: Some(String::from("FQhe")),
+            algorithm: String::from("MD5"),
+            qop: Some(String::from("auth")),
+        }), challenge);
+
+        assert_eq!(None, parse_digest_challenge("Basic realm=\"test\""));
+        assert_eq!(None, parse_digest_challenge("Digest realm=\"test\", nonce=\"a\", qop=\"auth-int\""));
+    }
+
+    #[test]
+    fn test_digest_response() {
+        // example of RFC 7616, section 3.9.1
+        let challenge = DigestChallenge {
+            realm: String::from("http-auth@example.org"),
+            nonce: String::from("7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v"),
+            opaque: Some(String::from("FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS")),
+            algorithm: String::from("MD5"),
+            qop: Some(String::from("auth")),
+        };
+        let header = digest_response(&challenge, "Mufasa", "Circle of Life", "GET", "/dir/index.html",
+            1, "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ");
+        assert!(header.contains("response=\"8ca523f5e9506fed4657c9700eebdbec\""));
+        assert!(header.contains("nc=00000001"));
+
+        let challenge = DigestChallenge { algorithm: String::from("SHA-256"), ..challenge };
+        let header = digest_response(&challenge, "Mufasa", "Circle of Life", "GET", "/dir/index.html",
+            1, "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ");
+        assert!(header.contains("response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\""));
+    }
+}
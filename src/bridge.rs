@@ -0,0 +1,555 @@
+use log::warn;
+use reqwest::dns::{Name, Resolve};
+use reqwest::Url;
+use rustls::pki_types::ServerName;
+use rustls::ClientConfig;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{copy, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+use crate::args::Args;
+use crate::resolve::{with_target, Resolver};
+
+/// Header used to authenticate connections to the bridge.
+pub const BRIDGE_TOKEN_HEADER: &str = "x-fetch-bridge";
+
+/// Maximum size of a request head read by the bridge.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Response of the bridge to CONNECT requests tunneled via SOCKS4.
+const CONNECTION_ESTABLISHED: &[u8] = b"HTTP/1.1 200 Connection established\r\n\r\n";
+
+/// Local endpoint of a bridge to an HTTPS or SOCKS4 proxy.
+pub struct ProxyBridge {
+    /// URL of the bridge, used as HTTP proxy.
+    pub url: Url,
+    /// Value of the BRIDGE_TOKEN_HEADER, which must be sent to the bridge.
+    pub token: String,
+}
+
+/// Protocol used by the bridge to connect to the proxy.
+pub enum BridgeProtocol {
+    /// HTTPS proxy, connections are forwarded via TLS using the config.
+    Https(Arc<ClientConfig>),
+    /// SOCKS4 proxy; host names are resolved by the proxy if remote_dns (socks4a).
+    Socks4 { remote_dns: bool, user_id: String },
+}
+
+/// Starts a local bridge to an HTTPS or SOCKS4 proxy.
+///
+/// reqwest connects to HTTPS proxies using the TLS settings of the servers, so the
+/// trust settings of the proxy (--proxy-cacert, ...) cannot be applied. Instead,
+/// reqwest uses the bridge as HTTP proxy and the bridge forwards each connection via
+/// TLS to the proxy. Each request has to carry the random token of the bridge, which
+/// is removed before the request is forwarded; connections sending a request without
+/// the token are closed, so other local processes cannot use the bridge.
+///
+/// The SOCKS4 client of reqwest always sends an empty user ID, so SOCKS4 proxies
+/// with a user ID are accessed via the bridge as well: the bridge connects to the
+/// target of the first request (CONNECT or absolute URL) via the SOCKS4 proxy.
+///
+/// The proxy is resolved like by reqwest (--resolve, -4, -6, but not --connect-to)
+/// and --connect-timeout applies to the connection to the proxy.
+pub fn start_bridge(proxy: &Url, protocol: BridgeProtocol, args: &Args) -> Result<ProxyBridge, String> {
+    let host = String::from(proxy.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']'));
+    let upstream = match protocol {
+        BridgeProtocol::Https(config) => Upstream::Tls {
+            server_name: ServerName::try_from(host.clone()).map_err(|_| format!("invalid proxy host: {}", host))?,
+            connector: TlsConnector::from(config),
+        },
+        BridgeProtocol::Socks4 { remote_dns, user_id } => Upstream::Socks4 { remote_dns, user_id },
+    };
+    let port = proxy.port_or_known_default().unwrap_or(match upstream {
+        Upstream::Tls { .. } => 443,
+        Upstream::Socks4 { .. } => 1080,
+    });
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        .and_then(TcpListener::from_std)
+        .map_err(|_| String::from("failed to create proxy bridge"))?;
+    let address = listener.local_addr().map_err(|_| String::from("failed to create proxy bridge"))?;
+
+    let mut token = [0u8; 16];
+    getrandom::getrandom(&mut token).map_err(|_| String::from("failed to create proxy bridge"))?;
+    let token = hex::encode(token);
+
+    let proxy = Arc::new(Proxy {
+        host,
+        port,
+        upstream,
+        resolver: Resolver::new(args)?,
+        connect_timeout: if args.connect_timeout > 0 { Some(Duration::from_secs(args.connect_timeout)) } else { None },
+        token: token.clone(),
+    });
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(forward(stream, proxy.clone()));
+        }
+    });
+
+    let url = Url::parse(&format!("http://{}", address)).map_err(|_| String::from("failed to create proxy bridge"))?;
+    Ok(ProxyBridge { url, token })
+}
+
+enum Upstream {
+    Tls {
+        server_name: ServerName<'static>,
+        connector: TlsConnector,
+    },
+    Socks4 {
+        remote_dns: bool,
+        user_id: String,
+    },
+}
+
+struct Proxy {
+    /// Host of the proxy (IPv6 addresses without brackets).
+    host: String,
+    port: u16,
+    upstream: Upstream,
+    resolver: Option<Resolver>,
+    connect_timeout: Option<Duration>,
+    token: String,
+}
+
+/// Stream to the proxy (TLS or TCP).
+trait ProxyStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ProxyStream for T {}
+
+impl Proxy {
+    /// Resolves the host; --resolve and --connect-to apply to the target of a request only.
+    async fn resolve(&self, host: &str, port: u16, target: Option<&Url>) -> Result<Vec<SocketAddr>, Error> {
+        let Some(resolver) = &self.resolver else {
+            return Ok(tokio::net::lookup_host((host, port)).await?.collect());
+        };
+
+        let name = Name::from_str(host).map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid host"))?;
+        let resolving = async { resolver.resolve(name).await };
+        let addrs = match target {
+            Some(target) => with_target(target, resolving).await,
+            None => resolving.await,
+        };
+        let addrs = addrs.map_err(|err| Error::new(ErrorKind::NotFound, err.to_string()))?;
+
+        // the port of the resolver is set, if --connect-to changed the port
+        Ok(addrs.map(|addr| SocketAddr::new(addr.ip(), if addr.port() != 0 { addr.port() } else { port })).collect())
+    }
+
+    /// Connects to the first reachable address of the proxy.
+    async fn connect(&self) -> Result<TcpStream, Error> {
+        let addrs = self.resolve(&self.host, self.port, None).await?;
+
+        let mut last_error = Error::new(ErrorKind::NotFound, "no address found");
+        for addr in addrs {
+            let result = match self.connect_timeout {
+                Some(duration) => timeout(duration, TcpStream::connect(addr)).await
+                    .unwrap_or_else(|_| Err(Error::new(ErrorKind::TimedOut, "connect timeout"))),
+                None => TcpStream::connect(addr).await,
+            };
+            match result {
+                Ok(stream) => { return Ok(stream); },
+                Err(err) => { last_error = err; }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connects to the proxy; SOCKS4 proxies connect to the target of the request head.
+    async fn connect_upstream(&self, head: &[u8]) -> Result<Box<dyn ProxyStream>, Error> {
+        match &self.upstream {
+            Upstream::Tls { server_name, connector } => {
+                let stream = self.connect().await?;
+                Ok(Box::new(connector.connect(server_name.clone(), stream).await?))
+            },
+            Upstream::Socks4 { remote_dns, user_id } => {
+                let target = get_target(head)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid request"))?;
+                Ok(Box::new(self.connect_socks4(&target, *remote_dns, user_id).await?))
+            },
+        }
+    }
+
+    /// Connects to the target via the SOCKS4 proxy.
+    ///
+    /// SOCKS4 supports IPv4 addresses only; SOCKS4a passes the host name as
+    /// address 0.0.0.1 followed by the host name.
+    async fn connect_socks4(&self, target: &Url, remote_dns: bool, user_id: &str) -> Result<TcpStream, Error> {
+        let host = target.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+        let port = target.port_or_known_default().unwrap_or(80);
+
+        let (addr, name) = match host.parse::<Ipv4Addr>() {
+            Ok(ip) => (SocketAddr::new(IpAddr::V4(ip), port), None),
+            Err(_) if remote_dns => (SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 1)), port), Some(host)),
+            Err(_) => {
+                let addr = self.resolve(host, port, Some(target)).await?.into_iter()
+                    .find(|addr| addr.is_ipv4())
+                    .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no IPv4 address found for {} (required by SOCKS4)", host)))?;
+                (addr, None)
+            },
+        };
+        let IpAddr::V4(ip) = addr.ip() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "SOCKS4 requires an IPv4 address"));
+        };
+
+        let mut request = vec![4, 1];
+        request.extend_from_slice(&addr.port().to_be_bytes());
+        request.extend_from_slice(&ip.octets());
+        request.extend_from_slice(user_id.as_bytes());
+        request.push(0);
+        if let Some(name) = name {
+            request.extend_from_slice(name.as_bytes());
+            request.push(0);
+        }
+
+        let mut stream = self.connect().await?;
+        stream.write_all(&request).await?;
+        let mut reply = [0u8; 8];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 90 {
+            return Err(Error::new(ErrorKind::ConnectionRefused, format!("SOCKS4 request rejected (code {})", reply[1])));
+        }
+        Ok(stream)
+    }
+}
+
+/// Forwards a local connection to the proxy; the token header is removed from each request.
+async fn forward(local: TcpStream, proxy: Arc<Proxy>) {
+    let (local_read, mut local_write) = local.into_split();
+    let mut local_read = BufReader::new(local_read);
+    let Some(head) = read_head(&mut local_read).await else {
+        return;
+    };
+    let Some(head) = remove_token(&head, &proxy.token) else {
+        return;
+    };
+
+    let remote = match proxy.connect_upstream(&head).await {
+        Ok(remote) => remote,
+        Err(err) => {
+            let protocol = match proxy.upstream {
+                Upstream::Tls { .. } => "HTTPS",
+                Upstream::Socks4 { .. } => "SOCKS4",
+            };
+            warn!("failed to connect to {} proxy {}:{}: {}", protocol, proxy.host, proxy.port, err);
+            return;
+        }
+    };
+    let (mut remote_read, mut remote_write) = tokio::io::split(remote);
+
+    // the SOCKS4 proxy is connected to the target, so CONNECT is answered by the bridge
+    // and requests are sent to the target
+    let is_socks = matches!(proxy.upstream, Upstream::Socks4 { .. });
+    let is_tunnel = is_socks && get_body_length(&head) == BodyLength::Tunnel;
+    if is_tunnel && local_write.write_all(CONNECTION_ESTABLISHED).await.is_err() {
+        return;
+    }
+
+    let requests = async {
+        if is_tunnel {
+            copy(&mut local_read, &mut remote_write).await.map(|_| ())
+        }
+        else {
+            forward_requests(&mut local_read, &mut remote_write, head, &proxy.token, is_socks).await
+        }
+    };
+    let responses = copy(&mut remote_read, &mut local_write);
+    tokio::select! {
+        _ = requests => {},
+        _ = responses => {},
+    }
+}
+
+/// Forwards the requests of a connection, starting with the (already checked) first head.
+///
+/// After CONNECT, the data is forwarded as is. Otherwise, the body of each request is
+/// forwarded according to its Content-Length or chunked encoding, so the token can be
+/// removed from the head of the next request. Requests sent to servers instead of
+/// proxies are converted to origin form (e.g. "GET /path HTTP/1.1").
+async fn forward_requests<R, W>(local: &mut R, remote: &mut W, head: Vec<u8>, token: &str, origin_form: bool) -> Result<(), Error>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut head = head;
+    loop {
+        if origin_form {
+            head = to_origin_form(&head)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid request"))?;
+        }
+        remote.write_all(&head).await?;
+        match get_body_length(&head) {
+            BodyLength::Tunnel => {
+                copy(local, remote).await?;
+                return Ok(());
+            },
+            BodyLength::Fixed(length) => {
+                copy(&mut local.take(length), remote).await?;
+            },
+            BodyLength::Chunked => {
+                forward_chunked(local, remote).await?;
+            },
+        }
+        remote.flush().await?;
+
+        let next = read_head(local).await
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "connection closed"))?;
+        head = remove_token(&next, token)
+            .ok_or_else(|| Error::new(ErrorKind::PermissionDenied, "missing bridge token"))?;
+    }
+}
+
+/// Returns the target of a request to a proxy: the authority of CONNECT or the
+/// absolute URL of other requests.
+fn get_target(head: &[u8]) -> Option<Url> {
+    let line = std::str::from_utf8(head).ok()?.split("\r\n").next()?;
+    let mut parts = line.split(' ');
+    let (method, target) = (parts.next()?, parts.next()?);
+    let url = match method {
+        "CONNECT" => Url::parse(&format!("https://{}", target)).ok()?,
+        _ => Url::parse(target).ok().filter(|url| url.scheme() == "http")?,
+    };
+    if url.has_host() { Some(url) } else { None }
+}
+
+/// Replaces the absolute URL of the request line by path and query.
+fn to_origin_form(head: &[u8]) -> Option<Vec<u8>> {
+    let head = std::str::from_utf8(head).ok()?;
+    let (line, rest) = head.split_once("\r\n")?;
+    let mut parts = line.splitn(3, ' ');
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+
+    let (_, authority_and_path) = target.split_once("://")?;
+    let path = match authority_and_path.find(['/', '?']) {
+        Some(index) if authority_and_path[index..].starts_with('/') => String::from(&authority_and_path[index..]),
+        Some(index) => format!("/{}", &authority_and_path[index..]),
+        None => String::from("/"),
+    };
+    Some(format!("{} {} {}\r\n{}", method, path, version, rest).into_bytes())
+}
+
+/// Forwards a chunked body including its trailers.
+async fn forward_chunked<R, W>(local: &mut R, remote: &mut W) -> Result<(), Error>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid chunked encoding");
+    loop {
+        let line = read_line(local).await?.ok_or_else(invalid)?;
+        remote.write_all(&line).await?;
+        let size = std::str::from_utf8(&line).ok()
+            .and_then(|line| u64::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or_else(invalid)?;
+        if size == 0 {
+            break;
+        }
+        // chunk data followed by CRLF
+        copy(&mut local.take(size + 2), remote).await?;
+    }
+
+    // trailers, terminated by an empty line
+    loop {
+        let line = read_line(local).await?.ok_or_else(invalid)?;
+        remote.write_all(&line).await?;
+        if line == b"\r\n" {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads a line including CRLF; None at the end of the stream.
+async fn read_line<R: AsyncBufRead + Unpin>(stream: &mut R) -> Result<Option<Vec<u8>>, Error> {
+    let mut line = Vec::new();
+    let count = (&mut *stream).take(MAX_HEAD_SIZE as u64).read_until(b'\n', &mut line).await?;
+    if count == 0 || !line.ends_with(b"\n") {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+/// Reads a request head (up to the empty line).
+async fn read_head<R: AsyncBufRead + Unpin>(stream: &mut R) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let line = read_line(stream).await.ok()??;
+        data.extend_from_slice(&line);
+        if data.len() > MAX_HEAD_SIZE {
+            return None;
+        }
+        if line == b"\r\n" {
+            return Some(data);
+        }
+    }
+}
+
+/// Length of the data following a request head.
+#[derive(Debug, PartialEq)]
+enum BodyLength {
+    /// CONNECT: the remaining data belongs to the tunnel.
+    Tunnel,
+    Fixed(u64),
+    Chunked,
+}
+
+fn get_body_length(head: &[u8]) -> BodyLength {
+    let head = String::from_utf8_lossy(head);
+    if head.starts_with("CONNECT ") {
+        return BodyLength::Tunnel;
+    }
+
+    let mut length = BodyLength::Fixed(0);
+    for (name, value) in head.split("\r\n").skip(1).filter_map(|line| line.split_once(':')) {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            if value.rsplit(',').next().is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked")) {
+                return BodyLength::Chunked;
+            }
+        }
+        else if name.eq_ignore_ascii_case("content-length") {
+            length = BodyLength::Fixed(value.trim().parse().unwrap_or(0));
+        }
+    }
+    length
+}
+
+/// Removes the token header from the request head.
+/// Returns None, if the head does not contain the token.
+fn remove_token(head: &[u8], token: &str) -> Option<Vec<u8>> {
+    let head = std::str::from_utf8(head).ok()?;
+
+    let mut found = false;
+    let mut result = Vec::with_capacity(head.len());
+    for line in head.split_inclusive("\r\n") {
+        let is_token = line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case(BRIDGE_TOKEN_HEADER) && is_equal(value.trim().as_bytes(), token.as_bytes())
+        });
+        if is_token {
+            found = true;
+        }
+        else {
+            result.extend_from_slice(line.as_bytes());
+        }
+    }
+    if found { Some(result) } else { None }
+}
+
+/// Compares two values in constant time (for equal lengths), so the token cannot
+/// be guessed from the time it takes to reject a request.
+fn is_equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_token() {
+        let request = b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\nX-Fetch-Bridge: abc\r\n\r\n";
+        assert_eq!(
+            Some(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n".to_vec()),
+            remove_token(request, "abc"));
+        assert_eq!(None, remove_token(request, "other"));
+        assert_eq!(None, remove_token(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n", "abc"));
+    }
+
+    #[test]
+    fn test_is_equal() {
+        assert!(is_equal(b"abc", b"abc"));
+        assert!(!is_equal(b"abc", b"abd"));
+        assert!(!is_equal(b"abc", b"abcd"));
+        assert!(is_equal(b"", b""));
+    }
+
+    #[tokio::test]
+    async fn test_read_head() {
+        let mut data = &b"GET / HTTP/1.1\r\nHost: a\r\n\r\nbody"[..];
+        assert_eq!(Some(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n".to_vec()), read_head(&mut data).await);
+        assert_eq!(b"body", data);
+
+        // incomplete head
+        assert_eq!(None, read_head(&mut &b"GET / HTTP/1.1\r\nHost: a\r\n"[..]).await);
+
+        // head larger than MAX_HEAD_SIZE
+        let mut data = b"GET / HTTP/1.1\r\n".to_vec();
+        while data.len() <= MAX_HEAD_SIZE {
+            data.extend_from_slice(b"X-Header: value\r\n");
+        }
+        data.extend_from_slice(b"\r\n");
+        assert_eq!(None, read_head(&mut &data[..]).await);
+    }
+
+    #[tokio::test]
+    async fn test_forward_chunked() {
+        // chunk extension and trailer
+        let body = b"4;ext=1\r\nbody\r\nA\r\n0123456789\r\n0\r\nX-Trailer: a\r\n\r\n";
+        let mut data = [&body[..], b"next"].concat();
+        let mut local = &data[..];
+        let mut remote = Vec::new();
+        forward_chunked(&mut local, &mut remote).await.unwrap();
+        assert_eq!(body.to_vec(), remote);
+        assert_eq!(b"next", local);
+
+        // invalid chunk size
+        let mut remote = Vec::new();
+        let result = forward_chunked(&mut &b"zz\r\nbody\r\n0\r\n\r\n"[..], &mut remote).await;
+        assert_eq!(ErrorKind::InvalidData, result.unwrap_err().kind());
+
+        // connection closed within the body
+        data.truncate(20);
+        let mut remote = Vec::new();
+        assert!(forward_chunked(&mut &data[..], &mut remote).await.is_err());
+    }
+
+    #[test]
+    fn test_get_body_length() {
+        assert_eq!(BodyLength::Tunnel, get_body_length(b"CONNECT example.com:443 HTTP/1.1\r\n\r\n"));
+        assert_eq!(BodyLength::Fixed(0), get_body_length(b"GET http://example.com/ HTTP/1.1\r\n\r\n"));
+        assert_eq!(BodyLength::Fixed(42), get_body_length(b"POST http://example.com/ HTTP/1.1\r\nContent-Length: 42\r\n\r\n"));
+        assert_eq!(BodyLength::Chunked, get_body_length(b"POST http://example.com/ HTTP/1.1\r\ntransfer-encoding: gzip, chunked\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_get_target() {
+        let url = get_target(b"CONNECT example.com:8443 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!((Some("example.com"), Some(8443)), (url.host_str(), url.port_or_known_default()));
+        let url = get_target(b"GET http://[::1]/ HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!((Some("[::1]"), Some(80)), (url.host_str(), url.port_or_known_default()));
+        assert_eq!(None, get_target(b"GET / HTTP/1.1\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_to_origin_form() {
+        assert_eq!(Some(b"GET /a?b HTTP/1.1\r\nHost: example.com\r\n\r\n".to_vec()),
+            to_origin_form(b"GET http://example.com/a?b HTTP/1.1\r\nHost: example.com\r\n\r\n"));
+        assert_eq!(Some(b"GET /?b HTTP/1.1\r\n\r\n".to_vec()), to_origin_form(b"GET http://example.com?b HTTP/1.1\r\n\r\n"));
+        assert_eq!(Some(b"GET / HTTP/1.1\r\n\r\n".to_vec()), to_origin_form(b"GET http://example.com HTTP/1.1\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_forward_requests() {
+        let requests = b"data\
+            POST http://example.com/ HTTP/1.1\r\nx-fetch-bridge: abc\r\ntransfer-encoding: chunked\r\n\r\n\
+            4\r\nbody\r\n0\r\n\r\n\
+            GET http://example.com/ HTTP/1.1\r\nx-fetch-bridge: abc\r\n\r\n\
+            GET http://example.com/ HTTP/1.1\r\n\r\n";
+        let first = b"POST http://example.com/ HTTP/1.1\r\ncontent-length: 4\r\n\r\n".to_vec();
+        let mut remote = Vec::new();
+        let result = forward_requests(&mut &requests[..], &mut remote, first, "abc", false).await;
+
+        // the request without token is not forwarded
+        assert_eq!(ErrorKind::PermissionDenied, result.unwrap_err().kind());
+        assert_eq!(b"POST http://example.com/ HTTP/1.1\r\ncontent-length: 4\r\n\r\ndata\
+            POST http://example.com/ HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n\
+            4\r\nbody\r\n0\r\n\r\n\
+            GET http://example.com/ HTTP/1.1\r\n\r\n".to_vec(), remote);
+    }
+}
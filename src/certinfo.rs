@@ -0,0 +1,310 @@
+use reqwest::{Response, Version};
+use rustls::crypto::cipher::{AeadKey, Iv, KeyBlockShape, MessageDecrypter, MessageEncrypter, Tls12AeadAlgorithm, Tls13AeadAlgorithm, UnsupportedOperationError};
+use rustls::crypto::CipherSuiteCommon;
+use rustls::pki_types::CertificateDer;
+use rustls::{quic, ConnectionTrafficSecrets, SupportedCipherSuite, Tls12CipherSuite, Tls13CipherSuite};
+use std::io::{stderr, Write};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use sha2::{Digest, Sha256};
+use x509_parser::extensions::GeneralName;
+use x509_parser::objects::{oid2abbrev, oid_registry};
+use x509_parser::prelude::{FromDer, X509Certificate};
+use x509_parser::time::ASN1Time;
+use x509_parser::x509::X509Name;
+
+use crate::args::Args;
+use crate::metrics::{get_metrics, update_metrics, Metrics, PeerCertificate};
+use crate::tls::get_cipher_suite_name;
+use crate::writeout::uses_tls_session;
+
+static SHOW_CERTS: AtomicBool = AtomicBool::new(false);
+
+/// Enables the output of the TLS session on stderr (--show-certs).
+pub fn init_show_certs(args: &Args) {
+    SHOW_CERTS.store(args.show_certs, Ordering::Relaxed);
+}
+
+/// Returns true, if the TLS session of the connections to servers is recorded
+/// (--show-certs or variables of --write-out like %{certs}).
+pub fn is_session_recorded(args: &Args) -> bool {
+    args.show_certs || uses_tls_session()
+}
+
+/// Writes the TLS session of the last connection to stderr (if --show-certs).
+pub fn show_certs() {
+    if SHOW_CERTS.load(Ordering::Relaxed) {
+        let text = format_tls_session(&get_metrics());
+        if !text.is_empty() {
+            let _ = stderr().write_all(text.as_bytes());
+        }
+    }
+}
+
+/// Records the certificates presented by the server, before they are verified.
+pub fn record_certificates(end_entity: &CertificateDer<'_>, intermediates: &[CertificateDer<'_>]) {
+    let certs: Vec<PeerCertificate> = std::iter::once(end_entity).chain(intermediates)
+        .filter_map(|der| parse_certificate(der))
+        .collect();
+    update_metrics(|metrics| metrics.certs = certs);
+}
+
+/// Records the ALPN protocol of a response received over TLS.
+///
+/// reqwest does not expose the protocol negotiated by ALPN, but HTTP/2 is used
+/// over TLS only if the server selected "h2" (unless --http2-prior-knowledge)
+/// and QUIC connections require "h3". Servers without ALPN support are shown
+/// as "http/1.1".
+pub fn record_alpn(response: &Response, args: &Args) {
+    let alpn = match response.version() {
+        _ if response.url().scheme() != "https" || args.http2_prior_knowledge => "",
+        Version::HTTP_3 => "h3",
+        Version::HTTP_2 => "h2",
+        _ => "http/1.1",
+    };
+    update_metrics(|metrics| metrics.alpn = String::from(alpn));
+}
+
+fn parse_certificate(der: &[u8]) -> Option<PeerCertificate> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let san = cert.subject_alternative_name().ok().flatten()
+        .map(|extension| extension.value.general_names.iter().filter_map(|name| match name {
+            GeneralName::DNSName(dns) => Some(format!("DNS:{}", dns)),
+            GeneralName::IPAddress(ip) => {
+                let ip = match ip.len() {
+                    4 => <[u8; 4]>::try_from(*ip).ok().map(IpAddr::from),
+                    16 => <[u8; 16]>::try_from(*ip).ok().map(IpAddr::from),
+                    _ => None,
+                };
+                ip.map(|ip| format!("IP:{}", ip))
+            },
+            GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+            GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+            _ => None,
+        }).collect())
+        .unwrap_or_default();
+    let fingerprint = Sha256::digest(der);
+
+    Some(PeerCertificate {
+        subject: format_name(cert.subject()),
+        issuer: format_name(cert.issuer()),
+        san,
+        not_before: format_cert_time(&cert.validity().not_before),
+        not_after: format_cert_time(&cert.validity().not_after),
+        sha256: fingerprint.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(":"),
+    })
+}
+
+pub fn format_name(name: &X509Name) -> String {
+    name.iter_attributes()
+        .map(|attribute| {
+            let oid = attribute.attr_type();
+            let key = oid2abbrev(oid, oid_registry()).map(String::from).unwrap_or_else(|_| oid.to_id_string());
+            let value = attribute.as_str().unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// Formats the time like OpenSSL, e.g. "Jan  1 00:00:00 2025 GMT".
+pub fn format_cert_time(time: &ASN1Time) -> String {
+    let time = time.to_datetime();
+    let month = time.month().to_string();
+    format!("{} {:>2} {:02}:{:02}:{:02} {} GMT",
+        &month[..3], time.day(), time.hour(), time.minute(), time.second(), time.year())
+}
+
+/// Formats the TLS session (used by --show-certs and %{certs}); empty if no TLS
+/// session was recorded.
+pub fn format_tls_session(metrics: &Metrics) -> String {
+    if metrics.certs.is_empty() && metrics.tls_version.is_empty() {
+        return String::new();
+    }
+
+    let unknown = |value: &str| if value.is_empty() { String::from("unknown") } else { String::from(value) };
+    let mut text = format!("TLS session: {}, cipher {}, ALPN {}\n",
+        unknown(&metrics.tls_version), unknown(&metrics.tls_cipher), unknown(&metrics.alpn));
+    for (index, cert) in metrics.certs.iter().enumerate() {
+        text.push_str(&format!("Certificate {}:\n", index));
+        text.push_str(&format!(" subject: {}\n", cert.subject));
+        text.push_str(&format!(" issuer: {}\n", cert.issuer));
+        if !cert.san.is_empty() {
+            text.push_str(&format!(" subject alt names: {}\n", cert.san.join(", ")));
+        }
+        text.push_str(&format!(" start date: {}\n", cert.not_before));
+        text.push_str(&format!(" expire date: {}\n", cert.not_after));
+        text.push_str(&format!(" SHA-256 fingerprint: {}\n", cert.sha256));
+    }
+    text
+}
+
+/// Wraps the cipher suites of the crypto provider of the rustls configuration
+/// created by fetch for servers, so the cipher suite and the TLS version of each
+/// connection are recorded when its keys are set up (QUIC connections included).
+///
+/// rustls reports the negotiated cipher suite by the connection only, which is
+/// not accessible through reqwest. Connections to proxies use another provider.
+/// The wrappers are created once per process.
+pub fn record_cipher_suites(suites: Vec<SupportedCipherSuite>) -> Vec<SupportedCipherSuite> {
+    suites.into_iter().map(|suite| match suite {
+        SupportedCipherSuite::Tls13(inner) => {
+            let aead_alg = Box::leak(Box::new(RecordingTls13Aead { suite, inner: inner.aead_alg }));
+            let quic = inner.quic.map(|quic| -> &'static dyn quic::Algorithm {
+                Box::leak(Box::new(RecordingQuicAlgorithm { suite, inner: quic }))
+            });
+            SupportedCipherSuite::Tls13(Box::leak(Box::new(Tls13CipherSuite {
+                common: copy_common(&inner.common),
+                hkdf_provider: inner.hkdf_provider,
+                aead_alg,
+                quic,
+            })))
+        },
+        SupportedCipherSuite::Tls12(inner) => {
+            let aead_alg = Box::leak(Box::new(RecordingTls12Aead { suite, inner: inner.aead_alg }));
+            SupportedCipherSuite::Tls12(Box::leak(Box::new(Tls12CipherSuite {
+                common: copy_common(&inner.common),
+                prf_provider: inner.prf_provider,
+                kx: inner.kx,
+                sign: inner.sign,
+                aead_alg,
+            })))
+        },
+    }).collect()
+}
+
+fn copy_common(common: &CipherSuiteCommon) -> CipherSuiteCommon {
+    CipherSuiteCommon {
+        suite: common.suite,
+        hash_provider: common.hash_provider,
+        confidentiality_limit: common.confidentiality_limit,
+    }
+}
+
+fn record_cipher_suite(suite: &SupportedCipherSuite) {
+    // e.g. TLSv1_3 -> TLSv1.3
+    let version = format!("{:?}", suite.version().version).replace('_', ".");
+    let cipher = get_cipher_suite_name(suite);
+    update_metrics(|metrics| {
+        metrics.tls_version = version;
+        metrics.tls_cipher = cipher;
+    });
+}
+
+struct RecordingTls13Aead {
+    suite: SupportedCipherSuite,
+    inner: &'static dyn Tls13AeadAlgorithm,
+}
+
+impl Tls13AeadAlgorithm for RecordingTls13Aead {
+    fn encrypter(&self, key: AeadKey, iv: Iv) -> Box<dyn MessageEncrypter> {
+        record_cipher_suite(&self.suite);
+        self.inner.encrypter(key, iv)
+    }
+
+    fn decrypter(&self, key: AeadKey, iv: Iv) -> Box<dyn MessageDecrypter> {
+        self.inner.decrypter(key, iv)
+    }
+
+    fn key_len(&self) -> usize {
+        self.inner.key_len()
+    }
+
+    fn extract_keys(&self, key: AeadKey, iv: Iv) -> Result<ConnectionTrafficSecrets, UnsupportedOperationError> {
+        self.inner.extract_keys(key, iv)
+    }
+
+    fn fips(&self) -> bool {
+        self.inner.fips()
+    }
+}
+
+struct RecordingTls12Aead {
+    suite: SupportedCipherSuite,
+    inner: &'static dyn Tls12AeadAlgorithm,
+}
+
+impl Tls12AeadAlgorithm for RecordingTls12Aead {
+    fn encrypter(&self, key: AeadKey, iv: &[u8], extra: &[u8]) -> Box<dyn MessageEncrypter> {
+        record_cipher_suite(&self.suite);
+        self.inner.encrypter(key, iv, extra)
+    }
+
+    fn decrypter(&self, key: AeadKey, iv: &[u8]) -> Box<dyn MessageDecrypter> {
+        self.inner.decrypter(key, iv)
+    }
+
+    fn key_block_shape(&self) -> KeyBlockShape {
+        self.inner.key_block_shape()
+    }
+
+    fn extract_keys(&self, key: AeadKey, iv: &[u8], explicit: &[u8]) -> Result<ConnectionTrafficSecrets, UnsupportedOperationError> {
+        self.inner.extract_keys(key, iv, explicit)
+    }
+
+    fn fips(&self) -> bool {
+        self.inner.fips()
+    }
+}
+
+struct RecordingQuicAlgorithm {
+    suite: SupportedCipherSuite,
+    inner: &'static dyn quic::Algorithm,
+}
+
+impl quic::Algorithm for RecordingQuicAlgorithm {
+    fn packet_key(&self, key: AeadKey, iv: Iv) -> Box<dyn quic::PacketKey> {
+        record_cipher_suite(&self.suite);
+        self.inner.packet_key(key, iv)
+    }
+
+    fn header_protection_key(&self, key: AeadKey) -> Box<dyn quic::HeaderProtectionKey> {
+        self.inner.header_protection_key(key)
+    }
+
+    fn aead_key_len(&self) -> usize {
+        self.inner.aead_key_len()
+    }
+
+    fn fips(&self) -> bool {
+        self.inner.fips()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::pem::PemObject;
+
+    #[test]
+    fn test_parse_certificate() {
+        let der = CertificateDer::from_pem_slice(include_bytes!("../test-utils/proxy-server/src/cert.pem")).unwrap();
+        let cert = parse_certificate(&der).unwrap();
+        assert!(cert.subject.contains("CN=localhost"));
+        assert!(cert.san.contains(&String::from("DNS:localhost")));
+        assert_eq!(32 * 3 - 1, cert.sha256.len());
+        assert!(cert.not_after.ends_with(" GMT"));
+        assert_eq!(None, parse_certificate(b"garbage"));
+    }
+
+    #[test]
+    fn test_format_tls_session() {
+        let mut metrics = get_metrics();
+        assert_eq!("", format_tls_session(&metrics));
+
+        metrics.tls_version = String::from("TLSv1.3");
+        metrics.tls_cipher = String::from("TLS_AES_256_GCM_SHA384");
+        metrics.certs = vec![PeerCertificate {
+            subject: String::from("CN=localhost"),
+            issuer: String::from("CN=ca"),
+            san: vec![String::from("DNS:localhost"), String::from("IP:127.0.0.1")],
+            not_before: String::from("Jan  1 00:00:00 2025 GMT"),
+            not_after: String::from("Jan  1 00:00:00 2035 GMT"),
+            sha256: String::from("AB:CD"),
+        }];
+        let text = format_tls_session(&metrics);
+        assert!(text.starts_with("TLS session: TLSv1.3, cipher TLS_AES_256_GCM_SHA384, ALPN unknown\nCertificate 0:\n"));
+        assert!(text.contains(" subject alt names: DNS:localhost, IP:127.0.0.1\n"));
+        assert!(text.ends_with(" SHA-256 fingerprint: AB:CD\n"));
+    }
+}
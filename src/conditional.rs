@@ -0,0 +1,209 @@
+use log::info;
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+use std::fs::File;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::args::Args;
+
+/// Time condition of -z; unmodified selects If-Unmodified-Since instead of If-Modified-Since.
+#[derive(Debug, PartialEq)]
+struct TimeCondition {
+    time: SystemTime,
+    unmodified: bool,
+}
+
+struct Conditions {
+    etag: Option<String>,
+    time: Option<TimeCondition>,
+}
+
+static CONDITIONS: OnceLock<Conditions> = OnceLock::new();
+
+/// Reads the ETag of --etag-compare and parses the time condition of --time-cond.
+pub fn init_conditions(args: &Args) -> Result<(), String> {
+    let etag = args.etag_compare.as_ref().and_then(|filename| {
+        let etag = read_etag(filename);
+        if etag.is_none() {
+            info!("no ETag found in {}, If-None-Match is not sent", filename);
+        }
+        etag
+    });
+
+    let time = match &args.time_cond {
+        Some(value) => Some(parse_time_cond(value)
+            .ok_or_else(|| format!("invalid --time-cond: {} (expected a date or a file name)", value))?),
+        None => None,
+    };
+
+    let _ = CONDITIONS.set(Conditions { etag, time });
+    Ok(())
+}
+
+/// Adds If-None-Match, If-Modified-Since or If-Unmodified-Since.
+pub fn add_conditions(mut request_builder: RequestBuilder) -> RequestBuilder {
+    if let Some(conditions) = CONDITIONS.get() {
+        if let Some(etag) = &conditions.etag {
+            request_builder = request_builder.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(condition) = &conditions.time {
+            let name = if condition.unmodified { header::IF_UNMODIFIED_SINCE } else { header::IF_MODIFIED_SINCE };
+            request_builder = request_builder.header(name, httpdate::fmt_http_date(condition.time));
+        }
+    }
+    request_builder
+}
+
+/// Returns true, if the response indicates that the document was not modified.
+/// This is the case for 304 and if the server ignored If-Modified-Since,
+/// but the Last-Modified header shows that the document is not newer.
+pub fn is_not_modified(response: &Response) -> bool {
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return true;
+    }
+
+    let condition = CONDITIONS.get().and_then(|conditions| conditions.time.as_ref());
+    match (condition, get_last_modified(response)) {
+        (Some(condition), Some(last_modified)) => {
+            response.status().is_success() && !condition.unmodified && last_modified <= condition.time
+        },
+        _ => false,
+    }
+}
+
+/// Returns the time of the Last-Modified header (if any).
+pub fn get_last_modified(response: &Response) -> Option<SystemTime> {
+    response.headers().get(header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+}
+
+/// Returns the ETag header of the response (if any).
+pub fn get_etag(response: &Response) -> Option<String> {
+    response.headers().get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// Writes the ETag to the file of --etag-save.
+pub fn save_etag(filename: &str, etag: &Option<String>) -> Result<(), String> {
+    let content = etag.as_ref().map(|etag| format!("{}\n", etag)).unwrap_or_default();
+    std::fs::write(filename, content)
+        .map_err(|_| format!("failed to write ETag file: {}", filename))
+}
+
+/// Sets the modification time of the file (see --remote-time).
+pub fn set_file_time(filename: &Path, time: SystemTime) -> Result<(), String> {
+    File::options().write(true).open(filename)
+        .and_then(|file| file.set_modified(time))
+        .map_err(|_| String::from("failed to set file time"))
+}
+
+fn read_etag(filename: &str) -> Option<String> {
+    let content = std::fs::read_to_string(filename).ok()?;
+    let etag = content.lines().next()?.trim();
+    if etag.is_empty() { None } else { Some(String::from(etag)) }
+}
+
+/// Parses the value of --time-cond: a HTTP date, an ISO 8601 date (UTC) or the
+/// modification time of an existing file; a leading '-' requests If-Unmodified-Since.
+fn parse_time_cond(value: &str) -> Option<TimeCondition> {
+    let (value, unmodified) = match value.strip_prefix('-') {
+        Some(value) => (value, true),
+        None => (value.strip_prefix('+').unwrap_or(value), false),
+    };
+
+    let time = match httpdate::parse_http_date(value.trim()).ok().or_else(|| parse_iso_date(value.trim())) {
+        Some(time) => time,
+        None => std::fs::metadata(value).ok()?.modified().ok()?,
+    };
+
+    Some(TimeCondition { time, unmodified })
+}
+
+/// Parses an ISO 8601 date in UTC: "2025-01-01", "2025-01-01T12:00",
+/// "2025-01-01T12:00:00" or "2025-01-01 12:00:00" (optionally followed by 'Z').
+fn parse_iso_date(value: &str) -> Option<SystemTime> {
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<u64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let days_in_month = match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if year < 1970 || day == 0 || day > days_in_month {
+        return None;
+    }
+
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let parts: Vec<&str> = time.split(':').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return None;
+        }
+        let mut parts = parts.iter().map(|part| part.parse::<u64>().ok());
+        let (hour, minute, second) = (parts.next()??, parts.next()??, parts.next().unwrap_or(Some(0))?);
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        seconds = hour * 3600 + minute * 60 + second;
+    }
+
+    // days since 1970-01-01 (see Howard Hinnant's days_from_civil)
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86_400 + seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_cond() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_735_689_600);
+        assert_eq!(Some(TimeCondition { time, unmodified: false }), parse_time_cond("Wed, 01 Jan 2025 00:00:00 GMT"));
+        assert_eq!(Some(TimeCondition { time, unmodified: true }), parse_time_cond("-Wed, 01 Jan 2025 00:00:00 GMT"));
+        assert_eq!(Some(TimeCondition { time, unmodified: true }), parse_time_cond("-2025-01-01"));
+        assert_eq!(None, parse_time_cond("yesterday"));
+    }
+
+    #[test]
+    fn test_parse_iso_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_735_689_600);
+        assert_eq!(Some(time), parse_iso_date("2025-01-01"));
+        assert_eq!(Some(time + Duration::from_secs(45_296)), parse_iso_date("2025-01-01T12:34:56Z"));
+        assert_eq!(Some(time + Duration::from_secs(45_240)), parse_iso_date("2025-01-01 12:34"));
+        assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(1_709_164_800)), parse_iso_date("2024-02-29"));
+        assert_eq!(Some(UNIX_EPOCH), parse_iso_date("1970-01-01T00:00:00"));
+        assert_eq!(None, parse_iso_date("2025-02-29"));
+        assert_eq!(None, parse_iso_date("2025-13-01"));
+        assert_eq!(None, parse_iso_date("2025-01-01T24:00"));
+        assert_eq!(None, parse_iso_date("2025-01"));
+        assert_eq!(None, parse_iso_date("2025-01-01T12"));
+    }
+
+    #[test]
+    fn test_read_etag() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "\"abc\"\n").unwrap();
+        assert_eq!(Some(String::from("\"abc\"")), read_etag(file.path().to_str().unwrap()));
+
+        std::fs::write(file.path(), "").unwrap();
+        assert_eq!(None, read_etag(file.path().to_str().unwrap()));
+    }
+}
@@ -0,0 +1,282 @@
+use flate2::write::GzDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
+use reqwest::{header, Response};
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Write};
+
+/// Value of the Accept-Encoding header used by `--compressed`.
+pub const ACCEPT_ENCODING: &str = "gzip, deflate, br, zstd";
+
+/// Size of the buffer used by the brotli decoder.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Size of the buffer used by the zlib decoder.
+const INFLATE_BUFFER_SIZE: usize = 32 * 1024;
+
+/// Receives the decoded data of a decoder.
+///
+/// Writes fail once more than limit bytes were decoded in total (--max-filesize),
+/// so a small compressed input cannot expand into memory without bounds
+/// (decompression bomb).
+pub struct Output {
+    data: Vec<u8>,
+    remaining: Option<u64>,
+}
+
+impl Output {
+    fn new(limit: u64) -> Self {
+        Output { data: Vec::new(), remaining: if limit > 0 { Some(limit) } else { None } }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if let Some(remaining) = &mut self.remaining {
+            if buf.len() as u64 > *remaining {
+                return Err(Error::new(ErrorKind::FileTooLarge, "decoded data exceeds the maximum file size"));
+            }
+            *remaining -= buf.len() as u64;
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Decoder of zlib data (Content-Encoding: deflate).
+///
+/// Unlike flate2::write::ZlibDecoder, it keeps track of the end of the stream,
+/// so truncated data is detected.
+pub struct Inflater {
+    decompress: Decompress,
+    output: Output,
+    finished: bool,
+}
+
+impl Inflater {
+    fn new(output: Output) -> Self {
+        Inflater { decompress: Decompress::new(true), output, finished: false }
+    }
+
+    fn finish(self) -> Result<Output, Error> {
+        if !self.finished {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete deflate stream"));
+        }
+        Ok(self.output)
+    }
+}
+
+impl Write for Inflater {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let mut buffer = [0; INFLATE_BUFFER_SIZE];
+        let mut input = buf;
+        // data after the end of the stream is ignored
+        while !self.finished {
+            let total_in = self.decompress.total_in();
+            let total_out = self.decompress.total_out();
+            let status = self.decompress.decompress(input, &mut buffer, FlushDecompress::None)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+            let produced = (self.decompress.total_out() - total_out) as usize;
+            input = &input[consumed..];
+            self.output.write_all(&buffer[..produced])?;
+            self.finished = status == Status::StreamEnd;
+
+            // a full buffer indicates further pending output
+            if produced < buffer.len() && (input.is_empty() || consumed == 0) {
+                break;
+            }
+        }
+        Ok(if self.finished { buf.len() } else { buf.len() - input.len() })
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Streaming decoder of the response body according to its Content-Encoding.
+///
+/// Each decoder writes the decoded data into its output,
+/// which is drained after each chunk of data passed to the decoder.
+pub enum Decoder {
+    Identity,
+    Gzip(GzDecoder<Output>),
+    Deflate(Box<Inflater>),
+    Brotli(Box<brotli::DecompressorWriter<Output>>),
+    Zstd(zstd::stream::zio::Writer<Output, zstd::stream::raw::Decoder<'static>>),
+}
+
+impl Decoder {
+    /// Creates a decoder for the Content-Encoding of a response; decoding fails
+    /// with ErrorKind::FileTooLarge once more than limit bytes are decoded (0 for
+    /// no limit).
+    pub fn from_response(response: &Response, limit: u64) -> Result<Decoder, String> {
+        let encoding = response.headers().get(header::CONTENT_ENCODING);
+        let encoding = match encoding {
+            Some(value) => value.to_str().map_err(|_| String::from("invalid content encoding"))?,
+            None => "identity",
+        };
+        Decoder::from_encoding(encoding, limit)
+    }
+
+    fn from_encoding(encoding: &str, limit: u64) -> Result<Decoder, String> {
+        let output = Output::new(limit);
+        match encoding.trim().to_lowercase().as_str() {
+            "" | "identity" => Ok(Decoder::Identity),
+            "gzip" | "x-gzip" => Ok(Decoder::Gzip(GzDecoder::new(output))),
+            "deflate" => Ok(Decoder::Deflate(Box::new(Inflater::new(output)))),
+            "br" => Ok(Decoder::Brotli(Box::new(brotli::DecompressorWriter::new(output, BROTLI_BUFFER_SIZE)))),
+            "zstd" => {
+                let decoder = zstd::stream::raw::Decoder::new()
+                    .map_err(|err| err.to_string())?;
+                Ok(Decoder::Zstd(zstd::stream::zio::Writer::new(output, decoder)))
+            },
+            other => Err(format!("unsupported content encoding: {}", other)),
+        }
+    }
+
+    /// Decodes a chunk of data and returns the decoded data available so far.
+    pub fn decode<'a>(&mut self, data: &'a [u8]) -> Result<Cow<'a, [u8]>, Error> {
+        let output = match self {
+            Decoder::Identity => { return Ok(Cow::Borrowed(data)); },
+            Decoder::Gzip(decoder) => { decoder.write_all(data)?; decoder.flush()?; decoder.get_mut() },
+            Decoder::Deflate(decoder) => { decoder.write_all(data)?; &mut decoder.output },
+            Decoder::Brotli(decoder) => { decoder.write_all(data)?; decoder.flush()?; decoder.get_mut() },
+            Decoder::Zstd(decoder) => { decoder.write_all(data)?; decoder.flush()?; decoder.writer_mut() },
+        };
+        Ok(Cow::Owned(std::mem::take(&mut output.data)))
+    }
+
+    /// Finishes decoding and returns the remaining decoded data; fails if the
+    /// encoded data is incomplete.
+    pub fn finish(self) -> Result<Vec<u8>, Error> {
+        let output = match self {
+            Decoder::Identity => { return Ok(Vec::new()); },
+            Decoder::Gzip(decoder) => decoder.finish()?,
+            Decoder::Deflate(decoder) => decoder.finish()?,
+            Decoder::Brotli(mut decoder) => {
+                decoder.close()?;
+                decoder.into_inner().map_err(|_| Error::new(ErrorKind::InvalidData, "incomplete brotli stream"))?
+            },
+            Decoder::Zstd(mut decoder) => {
+                decoder.finish()?;
+                decoder.into_inner().0
+            },
+        };
+        Ok(output.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use std::io::Read;
+    use flate2::Compression;
+
+    fn try_decode_all(mut decoder: Decoder, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        for chunk in data.chunks(7) {
+            result.extend_from_slice(&decoder.decode(chunk)?);
+        }
+        result.extend_from_slice(&decoder.finish()?);
+        Ok(result)
+    }
+
+    fn decode_all(decoder: Decoder, data: &[u8]) -> Vec<u8> {
+        try_decode_all(decoder, data).unwrap()
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"Hello, World!").unwrap();
+        let data = encoder.finish().unwrap();
+
+        let decoder = Decoder::from_encoding("gzip", 0).unwrap();
+        assert_eq!(b"Hello, World!".to_vec(), decode_all(decoder, &data));
+
+        let decoder = Decoder::from_encoding("gzip", 0).unwrap();
+        assert!(try_decode_all(decoder, &data[..data.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_decode_zstd() {
+        let data = zstd::encode_all(&b"Hello, World!"[..], 0).unwrap();
+
+        let decoder = Decoder::from_encoding("zstd", 0).unwrap();
+        assert_eq!(b"Hello, World!".to_vec(), decode_all(decoder, &data));
+
+        // truncated frame
+        let decoder = Decoder::from_encoding("zstd", 0).unwrap();
+        assert!(try_decode_all(decoder, &data[..data.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_decode_deflate() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"Hello, World!").unwrap();
+        let data = encoder.finish().unwrap();
+
+        let decoder = Decoder::from_encoding("deflate", 0).unwrap();
+        assert_eq!(b"Hello, World!".to_vec(), decode_all(decoder, &data));
+
+        let decoder = Decoder::from_encoding("deflate", 0).unwrap();
+        assert!(try_decode_all(decoder, &data[..data.len() - 2]).is_err());
+
+        // decoded data larger than the buffer of the decoder
+        let text = vec![b'x'; 3 * INFLATE_BUFFER_SIZE + 1];
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&text).unwrap();
+        let data = encoder.finish().unwrap();
+        let decoder = Decoder::from_encoding("deflate", 0).unwrap();
+        assert_eq!(text, decode_all(decoder, &data));
+    }
+
+    #[test]
+    fn test_decode_brotli() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut data, 4096, 5, 22);
+            encoder.write_all(b"Hello, World!").unwrap();
+        }
+
+        let decoder = Decoder::from_encoding("br", 0).unwrap();
+        assert_eq!(b"Hello, World!".to_vec(), decode_all(decoder, &data));
+
+        let decoder = Decoder::from_encoding("br", 0).unwrap();
+        assert!(try_decode_all(decoder, &data[..data.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_decode_limit() {
+        // 16 MB of zeros compress to a few kilobytes
+        let data = zstd::encode_all(std::io::repeat(0).take(16 * 1024 * 1024), 3).unwrap();
+        let mut decoder = Decoder::from_encoding("zstd", 1024 * 1024).unwrap();
+        let err = decoder.decode(&data).unwrap_err();
+        assert_eq!(ErrorKind::FileTooLarge, err.kind());
+
+        let mut data = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut data, 4096, 5, 22);
+            std::io::copy(&mut std::io::repeat(0).take(16 * 1024 * 1024), &mut encoder).unwrap();
+        }
+        let mut decoder = Decoder::from_encoding("br", 1024 * 1024).unwrap();
+        let err = decoder.decode(&data).unwrap_err();
+        assert_eq!(ErrorKind::FileTooLarge, err.kind());
+
+        let data = zstd::encode_all(&[0u8; 1024][..], 3).unwrap();
+        let decoder = Decoder::from_encoding("zstd", 1024).unwrap();
+        assert_eq!(vec![0u8; 1024], decode_all(decoder, &data));
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        assert!(matches!(Decoder::from_encoding("identity", 0).unwrap(), Decoder::Identity));
+        assert!(Decoder::from_encoding("compress", 0).is_err());
+    }
+}
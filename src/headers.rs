@@ -0,0 +1,118 @@
+use hyper::ext::ReasonPhrase;
+use log::warn;
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode, Version};
+use std::fs::File;
+use std::io::{stdout, Write};
+
+use crate::args::Args;
+use crate::metrics::format_version;
+
+/// Writes the headers of each received response (-i, -I and -D).
+pub struct HeaderOutput {
+    stdout: bool,
+    file: Option<File>,
+}
+
+impl HeaderOutput {
+    /// Headers are written to stdout with --include and --head
+    /// and to the file of --dump-header ('-' is stdout).
+    pub fn new(args: &Args) -> Result<Self, String> {
+        let mut output = HeaderOutput {
+            stdout: args.include || args.head,
+            file: None,
+        };
+
+        match args.dump_header.as_deref() {
+            Some("-") => { output.stdout = true; },
+            Some(filename) => {
+                let file = File::create(filename)
+                    .map_err(|_| format!("failed to create header file: {}", filename))?;
+                output.file = Some(file);
+            },
+            None => {}
+        }
+
+        Ok(output)
+    }
+
+    /// Writes status line and headers of the response.
+    pub fn write(&mut self, response: &Response) {
+        if !self.stdout && self.file.is_none() {
+            return;
+        }
+
+        let data = format_headers(response.version(), response.status(), get_reason(response), response.headers());
+        if self.stdout {
+            let mut stdout = stdout();
+            if stdout.write_all(&data).and_then(|_| stdout.flush()).is_err() {
+                warn!("failed to write headers");
+            }
+        }
+        if let Some(file) = &mut self.file {
+            if file.write_all(&data).is_err() {
+                warn!("failed to write header file");
+            }
+        }
+    }
+}
+
+/// Returns the reason phrase sent by the server, if it differs from the canonical
+/// one (e.g. "HTTP/1.1 200 Fine" instead of "HTTP/1.1 200 OK").
+pub fn get_reason(response: &Response) -> Option<&[u8]> {
+    response.extensions().get::<ReasonPhrase>().map(|reason| reason.as_bytes())
+}
+
+/// Formats status line and headers as received, e.g. "HTTP/1.1 200 OK".
+/// Header values are written as raw bytes, since they are not required to be valid UTF-8.
+/// Header names are lowercase, since their original case is not preserved.
+pub fn format_headers(version: Version, status: StatusCode, reason: Option<&[u8]>, headers: &HeaderMap) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(format!("HTTP/{} {}", format_version(version), status.as_str()).as_bytes());
+    // HTTP/2 and later do not transfer a reason phrase
+    if version < Version::HTTP_2 {
+        let reason = reason.or(status.canonical_reason().map(str::as_bytes));
+        if let Some(reason) = reason.filter(|reason| !reason.is_empty()) {
+            data.push(b' ');
+            data.extend_from_slice(reason);
+        }
+    }
+    data.extend_from_slice(b"\r\n");
+
+    for (name, value) in headers {
+        data.extend_from_slice(name.as_str().as_bytes());
+        data.extend_from_slice(b": ");
+        data.extend_from_slice(value.as_bytes());
+        data.extend_from_slice(b"\r\n");
+    }
+    data.extend_from_slice(b"\r\n");
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, CONTENT_TYPE, LOCATION};
+
+    #[test]
+    fn test_format_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, HeaderValue::from_static("/"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_bytes(b"text/plain; name=\xe4").unwrap());
+
+        let actual = format_headers(Version::HTTP_11, StatusCode::FOUND, None, &headers);
+        assert_eq!(b"HTTP/1.1 302 Found\r\nlocation: /\r\ncontent-type: text/plain; name=\xe4\r\n\r\n".to_vec(), actual);
+
+        let actual = format_headers(Version::HTTP_11, StatusCode::FOUND, Some(b"Moved"), &HeaderMap::new());
+        assert_eq!(b"HTTP/1.1 302 Moved\r\n\r\n".to_vec(), actual);
+
+        let actual = format_headers(Version::HTTP_11, StatusCode::FOUND, Some(b""), &HeaderMap::new());
+        assert_eq!(b"HTTP/1.1 302\r\n\r\n".to_vec(), actual);
+    }
+
+    #[test]
+    fn test_format_headers_http2() {
+        let actual = format_headers(Version::HTTP_2, StatusCode::OK, None, &HeaderMap::new());
+        assert_eq!(b"HTTP/2 200\r\n\r\n".to_vec(), actual);
+    }
+}
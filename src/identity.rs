@@ -0,0 +1,259 @@
+use p12_keystore::KeyStore;
+use pkcs8::der::asn1::{AnyRef, BitStringRef, ContextSpecific, ObjectIdentifier, OctetStringRef};
+use pkcs8::der::pem::LineEnding;
+use pkcs8::der::{Decode, Document, Encode, Reader, SliceReader, TagNumber};
+use pkcs8::{EncryptedPrivateKeyInfo, PrivateKeyInfo};
+use pkcs8::spki::AlgorithmIdentifierRef;
+use reqwest::Identity;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::args::Args;
+use crate::tls::TlsBackend;
+
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+
+#[derive(Debug, PartialEq)]
+enum CertType {
+    Pem,
+    Der,
+    P12,
+}
+
+/// Options of a client certificate: -E, --cert-type, --key and --pass
+/// or --proxy-cert, --proxy-cert-type, --proxy-key and --proxy-pass.
+pub struct CertOptions<'a> {
+    cert: &'a str,
+    cert_type: &'a Option<String>,
+    key: &'a Option<String>,
+    pass: &'a Option<String>,
+}
+
+impl<'a> CertOptions<'a> {
+    /// Returns the options of the client certificate used for servers (if any).
+    pub fn server(args: &'a Args) -> Option<Self> {
+        args.cert.as_deref().map(|cert| CertOptions { cert, cert_type: &args.cert_type, key: &args.key, pass: &args.pass })
+    }
+
+    /// Returns the options of the client certificate used for HTTPS proxies (if any).
+    pub fn proxy(args: &'a Args) -> Option<Self> {
+        args.proxy_cert.as_deref().map(|cert| CertOptions {
+            cert,
+            cert_type: &args.proxy_cert_type,
+            key: &args.proxy_key,
+            pass: &args.proxy_pass,
+        })
+    }
+}
+
+/// Loads the client certificate and its private key.
+///
+/// Certificates and keys are converted to PEM (PKCS#8 for the key), so
+/// encrypted keys and PKCS#12 bundles can be used with both TLS backends.
+pub fn load_identity(options: &CertOptions, backend: TlsBackend) -> Result<Identity, String> {
+    let (certs, key) = load_client_cert(options)?;
+
+    let mut cert_pem = String::new();
+    for cert in &certs {
+        cert_pem.push_str(&to_pem("CERTIFICATE", cert)?);
+    }
+    let key_pem = to_pem("PRIVATE KEY", key.secret_pkcs8_der())?;
+
+    let identity = match backend {
+        TlsBackend::Rustls => Identity::from_pem(format!("{}{}", cert_pem, key_pem).as_bytes()),
+        #[cfg(feature = "native-tls")]
+        TlsBackend::Native => Identity::from_pkcs8_pem(cert_pem.as_bytes(), key_pem.as_bytes()),
+        #[cfg(not(feature = "native-tls"))]
+        TlsBackend::Native => { return Err(String::from("the native TLS backend is not available in this build")); },
+    };
+    identity.map_err(|err| format!("failed to load client certificate: {}", err))
+}
+
+/// Loads the certificate chain and the private key (PKCS#8) of the client
+/// certificate and checks that the key belongs to the certificate.
+pub fn load_client_cert(options: &CertOptions) -> Result<(Vec<CertificateDer<'static>>, PrivatePkcs8KeyDer<'static>), String> {
+    let (filename, password) = split_cert_password(options.cert);
+    let passphrase = options.pass.clone().or(password);
+    let cert_type = get_cert_type(options.cert_type, &filename)?;
+
+    let data = std::fs::read(&filename)
+        .map_err(|_| format!("failed to read client certificate file: {}", filename))?;
+
+    let (certs, key) = match cert_type {
+        CertType::P12 => {
+            let bundle = KeyStore::from_pkcs12(&data, passphrase.as_deref().unwrap_or_default())
+                .map_err(|_| String::from("failed to load PKCS#12 bundle (wrong password?)"))?;
+            let (_, chain) = bundle.private_key_chain()
+                .ok_or("PKCS#12 bundle contains no private key with a certificate")?;
+            let certs = chain.chain().iter().map(|cert| CertificateDer::from(cert.as_der().to_vec())).collect();
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(chain.key().to_vec()));
+            (certs, key)
+        },
+        CertType::Pem | CertType::Der => {
+            let certs = if cert_type == CertType::Pem {
+                CertificateDer::pem_slice_iter(&data).collect::<Result<Vec<_>, _>>().ok().filter(|certs| !certs.is_empty())
+            }
+            else {
+                X509Certificate::from_der(&data).ok().map(|_| vec![CertificateDer::from(data.clone())])
+            };
+            let certs = certs.ok_or("failed to load client certificate")?;
+
+            let key_data = match options.key {
+                Some(key) => std::fs::read(key)
+                    .map_err(|_| format!("failed to read private key file: {}", key))?,
+                None => data,
+            };
+            let key = load_private_key(&key_data, passphrase.as_deref())?;
+            (certs, key)
+        }
+    };
+
+    let key = to_pkcs8(key)?;
+    let signing_key = ring::default_provider().key_provider.load_private_key(PrivateKeyDer::Pkcs8(key.clone_key()))
+        .map_err(|_| String::from("unsupported private key"))?;
+    let matches = match (X509Certificate::from_der(&certs[0]), signing_key.public_key()) {
+        (Ok((_, cert)), Some(public_key)) => cert.public_key().raw == public_key.as_ref(),
+        _ => false,
+    };
+    if !matches {
+        return Err(String::from("private key does not match client certificate"));
+    }
+
+    Ok((certs, key))
+}
+
+/// Loads a PEM or DER encoded private key, which may be encrypted (PKCS#8).
+fn load_private_key(data: &[u8], passphrase: Option<&str>) -> Result<PrivateKeyDer<'static>, String> {
+    let is_pem = data.windows(11).any(|window| window == b"-----BEGIN ");
+    let key = if is_pem {
+        let is_encrypted = data.windows(9).any(|window| window == b"ENCRYPTED");
+        let is_pkcs8 = data.windows(29).any(|window| window == b"BEGIN ENCRYPTED PRIVATE KEY--");
+        match passphrase {
+            _ if is_encrypted && !is_pkcs8 => { return Err(String::from("legacy encrypted PEM keys are not supported, use PKCS#8 (openssl pkcs8 -topk8)")); },
+            Some(passphrase) if is_encrypted => std::str::from_utf8(data).ok()
+                .and_then(|pem| Document::from_pem(pem).ok())
+                .and_then(|(_, der)| decrypt_private_key(der.as_bytes(), passphrase)),
+            None if is_encrypted => { return Err(String::from("private key is encrypted, but no passphrase specified (--pass)")); },
+            _ => PrivateKeyDer::from_pem_slice(data).ok(),
+        }
+    }
+    else {
+        match passphrase {
+            Some(passphrase) => decrypt_private_key(data, passphrase),
+            None => PrivateKeyDer::try_from(data.to_vec()).ok(),
+        }
+    };
+    key.ok_or_else(|| String::from("failed to load private key (wrong passphrase?)"))
+}
+
+/// Decrypts an encrypted PKCS#8 private key.
+fn decrypt_private_key(der: &[u8], passphrase: &str) -> Option<PrivateKeyDer<'static>> {
+    let key = EncryptedPrivateKeyInfo::from_der(der).ok()?.decrypt(passphrase).ok()?;
+    Some(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.as_bytes().to_vec())))
+}
+
+/// Converts a PKCS#1 (RSA) or SEC1 (EC) private key to PKCS#8, which is
+/// required by the native TLS backend.
+fn to_pkcs8(key: PrivateKeyDer<'static>) -> Result<PrivatePkcs8KeyDer<'static>, String> {
+    let der = match &key {
+        PrivateKeyDer::Pkcs1(key) => {
+            let algorithm = AlgorithmIdentifierRef { oid: RSA_ENCRYPTION, parameters: Some(AnyRef::NULL) };
+            PrivateKeyInfo::new(algorithm, key.secret_pkcs1_der()).to_der()
+        },
+        PrivateKeyDer::Sec1(key) => {
+            let curve = get_named_curve(key.secret_sec1_der())
+                .ok_or("failed to load private key (EC key without named curve)")?;
+            let algorithm = AlgorithmIdentifierRef { oid: EC_PUBLIC_KEY, parameters: Some(AnyRef::from(&curve)) };
+            PrivateKeyInfo::new(algorithm, key.secret_sec1_der()).to_der()
+        },
+        PrivateKeyDer::Pkcs8(key) => { return Ok(key.clone_key()); },
+        _ => { return Err(String::from("unsupported private key")); },
+    };
+    der.map(PrivatePkcs8KeyDer::from).map_err(|_| String::from("failed to convert private key"))
+}
+
+/// Returns the named curve of a SEC1 encoded EC private key (RFC 5915).
+fn get_named_curve(sec1: &[u8]) -> Option<ObjectIdentifier> {
+    let mut reader = SliceReader::new(sec1).ok()?;
+    reader.sequence(|reader| {
+        let _version = u8::decode(reader)?;
+        let _key = OctetStringRef::decode(reader)?;
+        let curve = ContextSpecific::<ObjectIdentifier>::decode_explicit(reader, TagNumber::N0)?;
+        let _public_key = ContextSpecific::<BitStringRef>::decode_explicit(reader, TagNumber::N1)?;
+        Ok(curve.map(|curve| curve.value))
+    }).ok().flatten()
+}
+
+/// Encodes DER data as PEM.
+fn to_pem(label: &str, der: &[u8]) -> Result<String, String> {
+    pkcs8::der::pem::encode_string(label, LineEnding::LF, der)
+        .map_err(|_| String::from("failed to convert client certificate"))
+}
+
+/// Splits "file:password"; a colon in the file name can be escaped by '\'.
+fn split_cert_password(value: &str) -> (String, Option<String>) {
+    let mut filename = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                match chars.next() {
+                    Some(':') => { filename.push(':'); },
+                    Some(c) => { filename.push('\\'); filename.push(c); },
+                    None => { filename.push('\\'); }
+                }
+            },
+            ':' => { return (filename, Some(chars.collect())); },
+            _ => { filename.push(c); }
+        }
+    }
+    (filename, None)
+}
+
+/// Returns the certificate type of --cert-type; .p12 and .pfx files are PKCS#12 by default.
+fn get_cert_type(cert_type: &Option<String>, filename: &str) -> Result<CertType, String> {
+    match cert_type.as_ref().map(|value| value.to_uppercase()).as_deref() {
+        Some("PEM") => Ok(CertType::Pem),
+        Some("DER") => Ok(CertType::Der),
+        Some("P12") => Ok(CertType::P12),
+        Some(other) => Err(format!("unsupported certificate type: {}", other)),
+        None => {
+            let filename = filename.to_lowercase();
+            if filename.ends_with(".p12") || filename.ends_with(".pfx") { Ok(CertType::P12) } else { Ok(CertType::Pem) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_cert_password() {
+        assert_eq!((String::from("client.pem"), None), split_cert_password("client.pem"));
+        assert_eq!((String::from("client.p12"), Some(String::from("se:cret"))), split_cert_password("client.p12:se:cret"));
+        assert_eq!((String::from("c:\\client.pem"), None), split_cert_password("c\\:\\client.pem"));
+    }
+
+    #[test]
+    fn test_get_cert_type() {
+        assert_eq!(Ok(CertType::Pem), get_cert_type(&None, "client.pem"));
+        assert_eq!(Ok(CertType::P12), get_cert_type(&None, "client.PFX"));
+        assert_eq!(Ok(CertType::Der), get_cert_type(&Some(String::from("der")), "client.crt"));
+        assert!(get_cert_type(&Some(String::from("ENG")), "client.crt").is_err());
+    }
+
+    #[test]
+    fn test_load_private_key() {
+        let encrypted = include_bytes!("../test/certs/client-encrypted.key");
+        let key = load_private_key(encrypted, Some("secret")).unwrap();
+        assert_eq!(key, load_private_key(include_bytes!("../test/certs/client.key"), None).unwrap());
+        assert!(load_private_key(encrypted, Some("wrong")).is_err());
+        assert_eq!(Err(String::from("private key is encrypted, but no passphrase specified (--pass)")),
+            load_private_key(encrypted, None));
+        assert!(to_pkcs8(key).is_ok());
+    }
+}
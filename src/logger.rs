@@ -1,6 +1,9 @@
 use log::{set_logger, set_max_level, Level, LevelFilter, Log};
+use std::sync::OnceLock;
 
 use crate::args::Args;
+use crate::metrics::update_metrics;
+use crate::trace::{self, trace_wire, WIRE_TARGET};
 
 struct Logger;
 
@@ -9,11 +12,27 @@ impl Log for Logger
 
     fn enabled(&self, metadata: &log::Metadata) -> bool {
         metadata.level() <= Level::Info
+            || (metadata.target() == WIRE_TARGET && trace::is_enabled())
     }
 
     fn log(&self, record: &log::Record) {
+        // connection data is passed to verbose / trace output
+        if record.target() == WIRE_TARGET {
+            if trace::is_enabled() {
+                trace_wire(&record.args().to_string());
+            }
+            return;
+        }
+
         if self.enabled(record.metadata()) {
-            eprintln!("{}: {}", record.level().as_str().to_lowercase(), record.args());
+            // error messages are recorded even in silent mode (see %{errormsg})
+            if record.level() == Level::Error {
+                update_metrics(|metrics| metrics.errormsg = record.args().to_string());
+            }
+
+            if record.level() <= *LEVEL.get().unwrap_or(&LevelFilter::Warn) {
+                eprintln!("{}: {}", record.level().as_str().to_lowercase(), record.args());
+            }
         }
     }
 
@@ -23,6 +42,7 @@ impl Log for Logger
 }
 
 static LOGGER: Logger = Logger { };
+static LEVEL: OnceLock<LevelFilter> = OnceLock::new();
 
 pub fn init_logger(args: &Args) {
     set_logger(&LOGGER).unwrap();
@@ -38,5 +58,12 @@ pub fn init_logger(args: &Args) {
         }    
     }
 
-    set_max_level(level);
+    let _ = LEVEL.set(level);
+    if args.verbose || args.trace.is_some() || args.trace_ascii.is_some() {
+        // connection data is logged at trace level
+        set_max_level(LevelFilter::Trace);
+    }
+    else {
+        set_max_level(level.max(LevelFilter::Error));
+    }
 }
\ No newline at end of file
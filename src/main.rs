@@ -1,30 +1,99 @@
 use clap::Parser;
-use log::{warn, error};
-use futures_util::StreamExt;
+use log::{info, warn, error};
 use logger::init_logger;
 use reqwest::tls::{CertificateRevocationList, Version};
 use reqwest::{ClientBuilder, redirect::Policy};
-use reqwest::{header, Certificate, Method, Proxy, Response};
-use reqwest::multipart::Form;
+use reqwest::{header, Certificate, Method, Response, Url};
 use std::fs::File;
 use std::path::PathBuf;
 use std::time::Duration;
-use std::process::{exit, ExitCode};
+use std::sync::Arc;
+use std::process::ExitCode;
 use std::io::{Write, Read};
 use sha2::{Sha256, Digest};
 use md5::Md5;
-use tokio::fs::File as TokioFile;
+use pkcs8::der::pem::{encode_string, LineEnding};
 
 mod logger;
 mod args;
+mod segments;
+mod decoder;
+mod throttle;
+mod progress;
+mod metrics;
+mod writeout;
+mod report;
+mod redirect;
+mod headers;
+mod request;
+mod trace;
+mod conditional;
+mod auth;
+mod netrc;
+mod identity;
+mod proxy;
+mod bridge;
+mod resolve;
+mod tls;
+mod ocsp;
+mod certinfo;
 
 use crate::args::Args;
+use crate::decoder::Decoder;
+use crate::metrics::{init_metrics, set_error_type, update_metrics, ErrorType};
+use crate::progress::Progress;
+use crate::throttle::{next_chunk, RateLimiter, SpeedCheck};
+use crate::segments::{supports_segments, download_segments};
+use crate::writeout::{init_write_out, write_out};
+use crate::report::{init_report, write_report};
+use crate::certinfo::{init_show_certs, show_certs};
+use crate::headers::HeaderOutput;
+use crate::request::fetch;
+use crate::trace::{init_trace, trace_info};
+use crate::auth::init_auth;
+use crate::proxy::get_proxies;
+use crate::resolve::Resolver;
+use crate::identity::{load_identity, CertOptions};
+use crate::tls::{create_verifier_config, requires_verifier_config, get_max_tls_version, get_tls_backend, install_crypto_provider, load_ca_certificates, load_crl_files, TlsBackend};
+use crate::conditional::{get_etag, get_last_modified, init_conditions, is_not_modified, save_etag, set_file_time};
+
+/// Exit code used when an operation timed out (same as curl).
+const EXIT_OPERATION_TIMEOUT: i32 = 28;
+
+/// Size of the pieces of response data passed to the decoder.
+const DECODE_CHUNK_SIZE: usize = 4 * 1024;
+
+/// Terminates the process after writing the --write-out output.
+fn exit(code: i32) -> ! {
+    show_certs();
+    write_out(code);
+    write_report(code);
+    let _ = std::io::stdout().flush();
+    std::process::exit(code)
+}
 
+#[derive(Clone, Copy)]
 struct Protocols {
     http: bool,
     https: bool,
 }
 
+impl Protocols {
+    /// Returns true, if the scheme of the URL is an allowed protocol.
+    fn allows(&self, url: &Url) -> bool {
+        match url.scheme() {
+            "http" => self.http,
+            "https" => self.https,
+            _ => false,
+        }
+    }
+
+    /// Returns the protocols allowed by both sets.
+    fn intersect(&self, other: &Protocols) -> Protocols {
+        Protocols { http: self.http && other.http, https: self.https && other.https }
+    }
+}
+
 fn get_request_method(args: & Args) -> Method {
     if let Some(request_method) = &args.request {
         let request_method = request_method.to_lowercase();
@@ -39,11 +108,15 @@ fn get_request_method(args: & Args) -> Method {
             "patch" => Method::PATCH,
             "trace" => Method::TRACE,
             _ => {
+                set_error_type(ErrorType::Usage);
                 error!("invalid request method");
                 exit(1);
             }
         }
     }
+    else if args.head {
+        Method::HEAD
+    }
     else if args.data.is_some() || args.data_raw.is_some() || !args.form.is_empty() {
             Method::POST
     }
@@ -65,21 +138,10 @@ fn get_protocols(protocols: &str) -> Protocols
                         "http" => { result = Protocols{http: true, https: false}; },
                         "https" => { result = Protocols{http: false, https: true}; },
                         _ => {
-                            warn!("unrecognized protocol \'{}''", protocol);
+                            warn!("unrecognized protocol \'{}\'", protocol);
                         }
                     }
                 },
-                '+' => {
-                    let protocol = &part[1..];
-                    match protocol {
-                        "all" => { result = Protocols{http: true, https: true}; },
-                        "http" => { result.http = true; },
-                        "https" => { result.https = true; },
-                        _ => {
-                            warn!("unrecognized protocol \'{}''", protocol);
-                        }
-                    };                
-                },
                 '-' => {
                     let protocol = &part[1..];
                     match protocol {
@@ -87,12 +149,21 @@ fn get_protocols(protocols: &str) -> Protocols
                         "http" => { result.http = false; },
                         "https" => { result.https = false; },
                         _ => {
-                            warn!("unrecognized protocol \'{}''", protocol);
+                            warn!("unrecognized protocol \'{}\'", protocol);
                         }
                     };                
                 },
                 _ => {
-                    warn!("unrecognized protocol \'{}''", part);
+                    // '+' is the default modifier
+                    let protocol = part.trim().strip_prefix('+').unwrap_or(part.trim());
+                    match protocol {
+                        "all" => { result = Protocols{http: true, https: true}; },
+                        "http" => { result.http = true; },
+                        "https" => { result.https = true; },
+                        _ => {
+                            warn!("unrecognized protocol \'{}\'", protocol);
+                        }
+                    };
                 }
             }
         }
@@ -100,6 +171,29 @@ fn get_protocols(protocols: &str) -> Protocols
     result
 }
 
+/// Returns the path of --unix-socket or --abstract-unix-socket (abstract names start with a NUL byte).
+fn get_unix_socket(args: &Args) -> Option<PathBuf> {
+    match (&args.unix_socket, &args.abstract_unix_socket) {
+        (Some(path), _) => Some(PathBuf::from(path)),
+        (_, Some(name)) => Some(PathBuf::from(format!("\0{}", name))),
+        _ => None,
+    }
+}
+
+/// Disables --http3 for connections, which do not support HTTP/3 (QUIC).
+/// Fails with --http3-only.
+fn disable_http3(args: &mut Args, connections: &str) {
+    if args.http3_only {
+        set_error_type(ErrorType::Usage);
+        error!("HTTP/3 is not supported with {}", connections);
+        exit(1);
+    }
+    if args.http3 {
+        info!("HTTP/3 is not supported with {}, falling back to HTTP/2", connections);
+        args.http3 = false;
+    }
+}
+
 fn get_filename(filename: &Option<String>) -> PathBuf {
     if let Some(name) = filename {
         PathBuf::from(name)
@@ -113,50 +207,156 @@ fn get_filename(filename: &Option<String>) -> PathBuf {
     }
 }
 
+struct Checksums {
+    md5: Md5,
+    sha256: Sha256,
+}
+
+impl Checksums {
+    fn new() -> Self {
+        Checksums { md5: Md5::new(), sha256: Sha256::new() }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.md5.update(data);
+        self.sha256.update(data);
+    }
+}
+
+fn write_data(file: &mut File, data: &[u8], count: &mut u64, args: &Args, filename: &PathBuf) {
+    *count += data.len() as u64;
+    if args.max_filesize > 0 && *count > args.max_filesize {
+        set_error_type(ErrorType::FileSize);
+        error!("content length too large: expected max. {} bytes, but {} bytes received", args.max_filesize, count);
+        let _ = std::fs::remove_file(filename);
+        exit(1);
+    }
+
+    if file.write_all(data).is_err() {
+        set_error_type(ErrorType::File);
+        error!("failed to write file");
+        let _ = std::fs::remove_file(filename);
+        exit(1);
+    }
+    update_metrics(|metrics| metrics.size_written += data.len() as u64);
+}
+
+/// Reports an error of the decoder, which fails once the decoded data exceeds --max-filesize.
+fn exit_decode_error(err: std::io::Error, args: &Args, filename: &PathBuf) -> ! {
+    if err.kind() == std::io::ErrorKind::FileTooLarge {
+        set_error_type(ErrorType::FileSize);
+        error!("content length too large: expected max. {} bytes", args.max_filesize);
+    }
+    else {
+        set_error_type(ErrorType::Decode);
+        error!("failed to decode response data");
+    }
+    let _ = std::fs::remove_file(filename);
+    exit(1);
+}
+
 async fn download(response: Response,args: &Args, filename: &PathBuf) {
+    let mut decoder = Decoder::Identity;
+    if args.compressed {
+        match Decoder::from_response(&response, args.max_filesize) {
+            Ok(value) => { decoder = value; },
+            Err(message) => {
+                set_error_type(ErrorType::Decode);
+                error!("{}", message);
+                exit(1);
+            }
+        }
+    }
+
     let file = std::fs::File::create(filename.clone());
     if file.is_err() {
+        set_error_type(ErrorType::File);
         error!("failed to create file");
         exit(1);
     }
     let mut file = file.unwrap();
 
-    let mut md5_hasher = Md5::new();
-    let mut sha256_hasher = Sha256::new();
+    let mut checksums = Checksums::new();
     let mut count : u64 = 0;
+    let mut progress = Progress::new(args, response.content_length());
+    let mut limiter = RateLimiter::new(args.limit_rate);
+    let mut speed_check = SpeedCheck::new(args);
     let mut stream = response.bytes_stream();
-    while let Some(item) = stream.next().await {
+    loop {
+        let item = next_chunk(&mut stream, &mut speed_check).await;
+        if let Err(message) = item {
+            set_error_type(ErrorType::Timeout);
+            error!("{}", message);
+            let _ = std::fs::remove_file(filename);
+            exit(EXIT_OPERATION_TIMEOUT);
+        }
+
+        let item = match item.unwrap() {
+            Some(item) => item,
+            None => { break; }
+        };
         if item.is_err() {
+            set_error_type(ErrorType::Transfer);
             error!("failed to read reponse data");
             std::fs::remove_file(filename).unwrap();
             exit(1);
         }
 
         let data = item.unwrap();
-        count += data.len() as u64;
-        if args.max_filesize > 0 && count > args.max_filesize {
-            error!("content length too large: expected max. {} bytes, but {} bytes received", args.max_filesize, count);
-            std::fs::remove_file(filename).unwrap();
-            exit(1);
+        update_metrics(|metrics| metrics.size_download += data.len() as u64);
+        if args.checksum_encoded {
+            checksums.update(data.as_ref());
         }
 
-        if file.write_all(data.as_ref()).is_err() {
-            error!("failed to write file");
-            std::fs::remove_file(filename).unwrap();
-            exit(1);
+        // decode in small pieces to keep track of the decoded size
+        for chunk in data.chunks(DECODE_CHUNK_SIZE) {
+            let decoded = decoder.decode(chunk);
+            if let Err(err) = decoded {
+                exit_decode_error(err, args, filename);
+            }
+
+            let decoded = decoded.unwrap();
+            write_data(&mut file, decoded.as_ref(), &mut count, args, filename);
+            if !args.checksum_encoded {
+                checksums.update(decoded.as_ref());
+            }
         }
 
-        sha256_hasher.update(data.as_ref());
-        md5_hasher.update(data.as_ref());
+        progress.update(data.len());
+        limiter.throttle(data.len()).await;
+    }
+    progress.finish();
+
+    let decoded = decoder.finish();
+    if let Err(err) = decoded {
+        exit_decode_error(err, args, filename);
     }
+    let decoded = decoded.unwrap();
+    write_data(&mut file, decoded.as_ref(), &mut count, args, filename);
+    if !args.checksum_encoded {
+        checksums.update(decoded.as_ref());
+    }
+
+    verify_checksums(args, checksums, filename);
+}
+
+fn verify_checksums(args: &Args, checksums: Checksums, filename: &PathBuf) {
+    let md5 = hex::encode(checksums.md5.finalize()).to_lowercase();
+    let sha256 = hex::encode(checksums.sha256.finalize()).to_lowercase();
+    let has_checksum = args.md5.is_some() || args.sha256.is_some();
+    update_metrics(|metrics| {
+        metrics.md5 = md5.clone();
+        metrics.sha256 = sha256.clone();
+        metrics.verified = if has_checksum { Some(false) } else { None };
+    });
 
     // check MD5
     if let Some(expected) = &args.md5 {
         let expected = expected.to_lowercase();
 
-        let hash = md5_hasher.finalize();
-        let actual = hex::encode(hash).to_lowercase();
+        let actual = md5;
         if  actual != expected {
+            set_error_type(ErrorType::Checksum);
             error!("MD5 checksum mismatch: expected {} but was {}",
                 expected, actual);
             std::fs::remove_file(filename).unwrap();
@@ -168,9 +368,9 @@ async fn download(response: Response,args: &Args, filename: &PathBuf) {
     if let Some(expected) = &args.sha256 {
         let expected = expected.to_lowercase();
 
-        let hash = sha256_hasher.finalize();
-        let actual = hex::encode(hash).to_lowercase();
+        let actual = sha256;
         if actual != expected {
+            set_error_type(ErrorType::Checksum);
             error!("SHA256 checksum mismatch: expected {} but was {}",
                 expected, actual);
             let _ = std::fs::remove_file(filename);
@@ -178,25 +378,63 @@ async fn download(response: Response,args: &Args, filename: &PathBuf) {
         }
     }
 
-
+    if has_checksum {
+        update_metrics(|metrics| metrics.verified = Some(true));
+    }
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let mut exit_code = ExitCode::SUCCESS;
-    let args = Args::parse();
+    let mut exit_code = 0;
+    let mut args = Args::parse();
     init_logger(&args);
+    init_metrics(&args.url);
+    if let Err(message) = init_write_out(&args) {
+        error!("{}", message);
+        return ExitCode::FAILURE;
+    }
+    init_show_certs(&args);
+    if let Err(message) = init_report(&args) {
+        error!("{}", message);
+        return ExitCode::FAILURE;
+    }
+    if let Err(message) = init_trace(&args) {
+        error!("{}", message);
+        return ExitCode::FAILURE;
+    }
+    if let Err(message) = init_conditions(&args) {
+        set_error_type(ErrorType::Usage);
+        error!("{}", message);
+        exit(1);
+    }
+    if let Err(message) = init_auth(&args) {
+        set_error_type(ErrorType::Usage);
+        error!("{}", message);
+        exit(1);
+    }
+
+    let mut header_output = match HeaderOutput::new(&args) {
+        Ok(header_output) => header_output,
+        Err(message) => {
+            set_error_type(ErrorType::File);
+            error!("{}", message);
+            exit(1);
+        }
+    };
 
     let request_method = get_request_method(&args);
 
     let mut builder = ClientBuilder::new();
 
-    // set redirect policy
-    if args.location {
-        builder = builder.redirect(Policy::limited(args.max_redirs));
-    }
-    else {
-        builder = builder.redirect(Policy::none());
+    // redirects are followed by fetch(), which checks --proto and --proto-redir;
+    // reqwest must not follow redirects on its own (e.g. to http:// with --proto =https)
+    builder = builder.redirect(Policy::none());
+
+    // verbose output and trace
+    if trace::is_enabled() {
+        builder = builder
+            .connection_verbose(true)
+            .tls_info(true);
     }
 
     // timeout
@@ -209,6 +447,19 @@ async fn main() -> ExitCode {
         builder = builder.connect_timeout(Duration::from_secs(args.connect_timeout));
     }
 
+    // host resolution (--resolve, --connect-to, -4, -6)
+    match Resolver::new(&args) {
+        Ok(Some(resolver)) => {
+            builder = builder.dns_resolver(Arc::new(resolver));
+        },
+        Ok(None) => {},
+        Err(message) => {
+            set_error_type(ErrorType::Usage);
+            error!("{}", message);
+            exit(1);
+        }
+    }
+
     // insecure
     if args.insecure {
         builder = builder
@@ -216,6 +467,36 @@ async fn main() -> ExitCode {
             .danger_accept_invalid_certs(true);
     }
 
+    // tls backend (TLS 1.3 and ALPN, which is used to negotiate HTTP/2, require rustls)
+    let tls_backend = match get_tls_backend(&args) {
+        Ok(tls_backend) => tls_backend,
+        Err(message) => {
+            set_error_type(ErrorType::Usage);
+            error!("{}", message);
+            exit(1);
+        }
+    };
+    trace_info(&format!("Using TLS backend {}", tls_backend.as_str()));
+    match tls_backend {
+        TlsBackend::Rustls => {
+            if let Err(message) = install_crypto_provider(&args) {
+                set_error_type(ErrorType::Usage);
+                error!("{}", message);
+                exit(1);
+            }
+            builder = builder.use_rustls_tls();
+        },
+        TlsBackend::Native => {
+            #[cfg(feature = "native-tls")]
+            {
+                builder = builder.use_native_tls();
+            }
+            if std::env::var_os("SSLKEYLOGFILE").is_some() {
+                warn!("SSLKEYLOGFILE is ignored by the native TLS backend (use --tls-backend rustls)");
+            }
+        },
+    }
+
     // tls
     if args.tlsv1 || args.tlsv1_0 {
         builder = builder.min_tls_version(Version::TLS_1_0);
@@ -227,186 +508,212 @@ async fn main() -> ExitCode {
         builder = builder.min_tls_version(Version::TLS_1_2);
     }
     if args.tlsv1_3 {
-        // TLS 1.3 requires rustls on some machines
-        // otherwise a build error occurs
-        builder = builder.use_rustls_tls()
-            .min_tls_version(Version::TLS_1_3);
+        builder = builder.min_tls_version(Version::TLS_1_3);
     }
-
-    // protocols
-    // Note that we only parse protocols to determine
-    // if http_only can be enabled. The underlying
-    // library does not allow to disbale https.
-    let protocols = get_protocols(&args.proto);
-    if !protocols.http && protocols.https {
-        builder = builder.https_only(true);
+    if let Some(version) = get_max_tls_version(&args, tls_backend) {
+        builder = builder.max_tls_version(version);
     }
 
-    // proxy
-    if let Some(ref proxy) = args.proxy {
-        builder = builder.proxy(Proxy::http(proxy).unwrap());
+    // http version (requests use HTTP/3 only if they ask for it, see request.rs)
+    if args.http1_1 {
+        builder = builder.http1_only();
+    }
+    if args.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    #[cfg(feature = "http3")]
+    if args.http3_only {
+        builder = builder.http3_prior_knowledge();
+    }
+    #[cfg(not(feature = "http3"))]
+    if args.http3_only {
+        set_error_type(ErrorType::Usage);
+        error!("HTTP/3 is not supported by this build (cargo feature http3)");
+        exit(1);
+    }
+    #[cfg(not(feature = "http3"))]
+    if args.http3 {
+        warn!("HTTP/3 is not supported by this build, falling back to HTTP/2");
     }
 
-    // CA certificate
-    if let Some(ref cacert) = args.cacert {
-        let file = File::open(cacert);
-        if file.is_err() {
-            eprintln!("failed to open CA certificate file");
-            return ExitCode::FAILURE;
+    // unix domain socket (the URL still provides Host header and path)
+    if let Some(path) = get_unix_socket(&args) {
+        disable_http3(&mut args, "unix domain sockets");
+        #[cfg(unix)]
+        {
+            builder = builder.unix_socket(path);
         }
-
-        let mut file = file.unwrap();
-        let mut data : Vec<u8> = vec!();
-        if file.read_to_end(&mut data).is_err() {
-            eprintln!("failed to read CA certifcate file");
-            return ExitCode::FAILURE;
+        #[cfg(not(unix))]
+        {
+            set_error_type(ErrorType::Usage);
+            error!("unix domain sockets are not supported on this platform: {}", path.display());
+            exit(1);
         }
+    }
 
-        if cacert.ends_with(".der") {
-            let cert = Certificate::from_der(data.as_ref());
-            if cert.is_err() {
-                eprintln!("failed to load DER certificate");
-                return ExitCode::FAILURE;
-            }
-            let cert = cert.unwrap();
-            builder = builder.add_root_certificate(cert);
-        }
-        else {
-            let certs = Certificate::from_pem_bundle(data.as_ref());
-            if certs.is_err() {
-                eprintln!("failed to load PEM certificate bundle");
-                return ExitCode::FAILURE;
-            }
-            let certs = certs.unwrap();
-            for cert in certs {
-                builder = builder.add_root_certificate(cert);
+    // proxy
+    match get_proxies(&args) {
+        Ok(proxies) if proxies.is_empty() => {
+            // the environment is evaluated by get_proxies
+            builder = builder.no_proxy();
+        },
+        Ok(proxies) => {
+            disable_http3(&mut args, "proxies");
+            for proxy in proxies {
+                builder = builder.proxy(proxy);
             }
+        },
+        Err(message) => {
+            set_error_type(ErrorType::Usage);
+            error!("{}", message);
+            exit(1);
         }
     }
 
-    // CRL
-    if let Some(ref crlfile) = args.crlfile {
-        let file = File::open(crlfile);
-        if file.is_err() {
-            eprintln!("failed to open CRL file");
-            return ExitCode::FAILURE;
-        }
-
-        let mut file = file.unwrap();
-        let mut data : Vec<u8> = vec!();
-        if file.read_to_end(&mut data).is_err() {
-            eprintln!("failed to read CRL file");
-            return ExitCode::FAILURE;
-        }
-    
-        let crls = CertificateRevocationList::from_pem_bundle(data.as_ref());
-        if crls.is_err() {
-            eprintln!("failed to load CRL(s)");
-            return ExitCode::FAILURE;
+    // CA certificates
+    let ca_certs = load_ca_certificates(&args, tls_backend);
+    if let Err(message) = ca_certs {
+        set_error_type(ErrorType::Tls);
+        error!("{}", message);
+        exit(1);
+    }
+    for der in ca_certs.unwrap() {
+        let cert = Certificate::from_der(&der);
+        if cert.is_err() {
+            set_error_type(ErrorType::Tls);
+            error!("failed to load CA certificate");
+            exit(1);
         }
-        let crls = crls.unwrap();
+        builder = builder.add_root_certificate(cert.unwrap());
+    }
 
-        builder = builder.add_crls(crls);
+    // built-in CA certificates (--ca-native replaces the bundled certificates of rustls)
+    if args.no_system_ca || (args.ca_native && tls_backend == TlsBackend::Rustls) {
+        builder = builder.tls_built_in_root_certs(false);
     }
 
-    let client = builder.build();
-    if client.is_err() {
-        error!("failed to create http client");
+    // CRL (reqwest accepts PEM only)
+    let crls = load_crl_files(&args.crlfile);
+    if let Err(message) = crls {
+        set_error_type(ErrorType::Tls);
+        error!("{}", message);
         exit(1);
     }
-    let client = client.unwrap();
-    let mut request_builder = client.request(request_method, args.url.clone());
-
-    // additional headers
-    for x in &args.header {
-        if let Some((name, value)) = x.split_once(':') {
-            let name = name.trim();
-            let value = value.trim();
-
-            request_builder = request_builder.header(name, value);
+    for der in crls.unwrap() {
+        let crls = encode_string("X509 CRL", LineEnding::LF, &der)
+            .ok()
+            .and_then(|pem| CertificateRevocationList::from_pem_bundle(pem.as_bytes()).ok());
+        if crls.is_none() {
+            set_error_type(ErrorType::Tls);
+            error!("failed to load CRL(s)");
+            exit(1);
         }
+        builder = builder.add_crls(crls.unwrap());
     }
 
-    // user agent
-    if let Some(ref user_agent) = args.user_agent {
-        request_builder = request_builder.header(header::USER_AGENT, user_agent);
-    }
-
-    // data
-    if let Some(ref data) = args.data {
-        if let Some(filename) = data.strip_prefix('@') {
-            let file = TokioFile::open(filename).await;
-            if let Ok(file) = file {
-                request_builder = request_builder.body(file);
-            }
-            else {
-                warn!("failed to open file, this results in an empty request body");
-            }
-        } else {
-            request_builder = request_builder.body(data.clone());
+    // client certificate
+    if let Some(options) = CertOptions::server(&args) {
+        let identity = load_identity(&options, tls_backend);
+        if let Err(message) = identity {
+            set_error_type(ErrorType::Tls);
+            error!("{}", message);
+            exit(1);
         }
+        builder = builder.identity(identity.unwrap());
     }
-    else if let Some(ref data) = args.data_raw {
-        request_builder = request_builder.body(data.clone());
-    }
-    // multipart data
-    else if !args.form.is_empty() {
-        let mut form_data = Form::new();
-        for key_value_pair in &args.form {
-            if let Some((key, value)) = key_value_pair.split_once('=') {
-                let key = key.trim();
 
-                form_data = form_data.text(String::from(key), String::from(value));
-            }
+    // public key pinning, OCSP stapling, --show-certs, ALPN and SSLKEYLOGFILE (replaces the TLS settings above)
+    if requires_verifier_config(&args, tls_backend) {
+        let config = create_verifier_config(&args);
+        if let Err(message) = config {
+            set_error_type(ErrorType::Tls);
+            error!("{}", message);
+            exit(1);
         }
-        request_builder = request_builder.multipart(form_data);
+        builder = builder.use_preconfigured_tls(config.unwrap());
     }
 
-    let response = request_builder.send().await;
-    if let Err(err) = response {
-        error!("{}", err);
+    let client = builder.build();
+    if client.is_err() {
+        set_error_type(ErrorType::Usage);
+        error!("failed to create http client");
         exit(1);
     }
-    let response = response.unwrap();
+    let client = client.unwrap();
+    let fetched = fetch(&client, &args, request_method, &mut header_output).await;
+    let response = fetched.response;
 
-    // print response headers
-    if args.include {
-        println!("{:?} {}", response.version(), response.status());
-        for (header, value) in response.headers().into_iter() {
-            println!("{}: {}", header, value.to_str().unwrap());
-        }
-        println!();
+    // keep the output file untouched if the document was not modified
+    if is_not_modified(&response) {
+        info!("document not modified, download skipped");
+        exit(exit_code);
     }
 
     let status = response.status();
     if !status.is_success() {
         if args.fail {
+            set_error_type(ErrorType::Http);
             error!("bad http status: {}", status.as_u16());
             exit(1);
         }
         if args.fail_with_body {
+            set_error_type(ErrorType::Http);
             error!("bad http status: {}", status.as_u16());
-            exit_code = ExitCode::FAILURE;
+            exit_code = 1;
         }
     
     }
 
-    if args.max_filesize > 0 {
+    // --head shows the headers only
+    if args.head {
+        exit(exit_code);
+    }
+
+    // the content length of encoded data does not limit the size of decoded data,
+    // so max_filesize is checked during download only
+    let is_encoded = response.headers().contains_key(header::CONTENT_ENCODING);
+    if args.max_filesize > 0 && !(args.compressed && is_encoded) {
         if let Some(content_length) = response.content_length() {
             if content_length > args.max_filesize {
+                set_error_type(ErrorType::FileSize);
                 error!("content length too large: {} bytes max. expected, but {} bytes content length", args.max_filesize, content_length);
                 exit(1);
             }
         }
     }
 
+    let etag = get_etag(&response);
+    let last_modified = get_last_modified(&response);
     let filename = get_filename(&args.output);
-    download(response, &args, &filename).await;
+    if args.segments > 1 && fetched.method == Method::GET && supports_segments(&response) {
+        download_segments(&client, &args, fetched.strip_credentials, response, &filename).await;
+    }
+    else {
+        download(response, &args, &filename).await;
+    }
+
+    if let Some(ref etag_file) = args.etag_save {
+        if status.is_success() {
+            if let Err(message) = save_etag(etag_file, &etag) {
+                set_error_type(ErrorType::File);
+                error!("{}", message);
+                exit(1);
+            }
+        }
+    }
 
+    if args.remote_time && args.output.is_some() {
+        if let Some(last_modified) = last_modified {
+            if let Err(message) = set_file_time(&filename, last_modified) {
+                warn!("{}", message);
+            }
+        }
+    }
     
     if args.output.is_none() {
         let file = File::open(filename.clone());
         if file.is_err() {
+            set_error_type(ErrorType::File);
             error!("failed to open file");
             exit(1);
         }
@@ -416,6 +723,7 @@ async fn main() -> ExitCode {
         loop {
             let result = file.read(&mut buffer);
             if result.is_err() {
+                set_error_type(ErrorType::File);
                 error!("failed to read file");
                 let _ = std::fs::remove_file(filename);
                 exit(1);
@@ -434,7 +742,7 @@ async fn main() -> ExitCode {
         let _ = std::fs::remove_file(filename);
     }
 
-    exit_code
+    exit(exit_code)
 }
 
 #[cfg(test)]
@@ -458,30 +766,101 @@ mod tests {
             request: method,
             header: Vec::new(),
             user_agent: None,
-            data: data,
+            user: None,
+            basic: false,
+            digest: false,
+            oauth2_bearer: None,
+            netrc: false,
+            netrc_optional: false,
+            netrc_file: None,
+            location_trusted: false,
+            data,
             data_raw: None,
             form: Vec::new(),
             insecure: false,
             location: false,
             max_redirs: 0,
+            redirect_allow_host: vec!(),
+            post301: false,
+            post302: false,
+            post303: false,
             max_filesize: 0,
+            compressed: false,
+            checksum_encoded: false,
             connect_timeout: 0,
+            resolve: vec![],
+            connect_to: vec![],
+            ipv4: false,
+            ipv6: false,
+            unix_socket: None,
+            abstract_unix_socket: None,
             max_time: 0,
+            segments: 1,
+            limit_rate: None,
+            speed_limit: None,
+            speed_time: None,
             tlsv1: false,
             tlsv1_0: false,
             tlsv1_1: false,
             tlsv1_2: false,
             tlsv1_3: false,
+            tls_max: None,
+            ciphers: None,
+            tls13_ciphers: None,
+            curves: None,
+            tls_backend: None,
+            http1_1: false,
+            http2: false,
+            http2_prior_knowledge: false,
+            http3: false,
+            http3_only: false,
             proto: String::from(""),
+            proto_redir: String::from(""),
             silent: false,
             show_error: false,
             verbose: false,
+            trace: None,
+            trace_ascii: None,
+            trace_time: false,
+            progress_bar: false,
+            no_progress_meter: false,
+            etag_compare: None,
+            etag_save: None,
+            time_cond: None,
+            remote_time: false,
             include: false,
+            dump_header: None,
+            head: false,
             fail: false,
             fail_with_body: false,
+            write_out: None,
+            report: None,
             proxy: None,
-            cacert: None,
-            crlfile: None,
+            socks5: None,
+            socks5_hostname: None,
+            proxy_user: None,
+            proxy_header: vec!(),
+            noproxy: None,
+            ignore_proxy_env: false,
+            cacert: vec![],
+            capath: None,
+            ca_native: false,
+            no_system_ca: false,
+            pinnedpubkey: None,
+            cert_status: false,
+            show_certs: false,
+            cert: None,
+            cert_type: None,
+            key: None,
+            pass: None,
+            crlfile: vec![],
+            proxy_cacert: None,
+            proxy_crlfile: None,
+            proxy_insecure: false,
+            proxy_cert: None,
+            proxy_cert_type: None,
+            proxy_key: None,
+            proxy_pass: None,
             sha256: None,
             md5: None,
         }
@@ -524,9 +903,30 @@ mod tests {
 
         let args = args_from_method(None, None);
         assert_eq!(Method::GET, get_request_method(&args));
+
+        let mut args = args_from_method(None, None);
+        args.head = true;
+        assert_eq!(Method::HEAD, get_request_method(&args));
+
+        let mut args = args_from_method(Some(String::from("GET")), None);
+        args.head = true;
+        assert_eq!(Method::GET, get_request_method(&args));
+    }
+
+    #[test]
+    fn test_get_unix_socket() {
+        let mut args = args_from_method(None, None);
+        assert_eq!(None, get_unix_socket(&args));
+
+        args.abstract_unix_socket = Some(String::from("daemon"));
+        assert_eq!(Some(PathBuf::from("\0daemon")), get_unix_socket(&args));
+
+        args.unix_socket = Some(String::from("/run/daemon.sock"));
+        assert_eq!(Some(PathBuf::from("/run/daemon.sock")), get_unix_socket(&args));
     }
 
     #[test]
+    #[allow(clippy::unnecessary_owned_empty_strings)]
     fn test_get_protocols() {
         let protocols = get_protocols(&String::from(""));
         assert!(protocols.http);
@@ -557,4 +957,27 @@ mod tests {
         assert!(!protocols.https);
 
     }
+
+    #[test]
+    fn test_get_protocols_without_modifier() {
+        let protocols = get_protocols("-all,https");
+        assert!(!protocols.http);
+        assert!(protocols.https);
+    }
+
+    #[test]
+    fn test_protocols_allows() {
+        let http = Url::parse("http://example.com/").unwrap();
+        let https = Url::parse("https://example.com/").unwrap();
+        let ftp = Url::parse("ftp://example.com/").unwrap();
+
+        let protocols = get_protocols("=https");
+        assert!(!protocols.allows(&http));
+        assert!(protocols.allows(&https));
+        assert!(!get_protocols("").allows(&ftp));
+
+        let protocols = get_protocols("").intersect(&get_protocols("-https"));
+        assert!(protocols.allows(&http));
+        assert!(!protocols.allows(&https));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,199 @@
+use reqwest::header::HeaderMap;
+use reqwest::Version;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+/// Category of the error which terminated the transfer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorType {
+    Usage,
+    Connection,
+    Timeout,
+    Tls,
+    Redirect,
+    Http,
+    FileSize,
+    Decode,
+    Transfer,
+    Checksum,
+    File,
+}
+
+impl ErrorType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorType::Usage => "usage",
+            ErrorType::Connection => "connection",
+            ErrorType::Timeout => "timeout",
+            ErrorType::Tls => "tls",
+            ErrorType::Redirect => "redirect",
+            ErrorType::Http => "http",
+            ErrorType::FileSize => "file_size",
+            ErrorType::Decode => "decode",
+            ErrorType::Transfer => "transfer",
+            ErrorType::Checksum => "checksum",
+            ErrorType::File => "file",
+        }
+    }
+
+    pub fn from_reqwest(err: &reqwest::Error) -> Self {
+        if err.is_timeout() {
+            ErrorType::Timeout
+        }
+        else if err.is_redirect() {
+            ErrorType::Redirect
+        }
+        else if err.is_connect() {
+            ErrorType::Connection
+        }
+        else if err.is_builder() {
+            ErrorType::Usage
+        }
+        else {
+            ErrorType::Transfer
+        }
+    }
+}
+
+/// A redirect followed during the transfer.
+#[derive(Clone)]
+pub struct Redirect {
+    pub status: u16,
+    pub url: String,
+    pub location: String,
+}
+
+/// A certificate presented by the server (--show-certs, %{certs}).
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerCertificate {
+    pub subject: String,
+    pub issuer: String,
+    /// Subject alternative names, e.g. "DNS:example.com".
+    pub san: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    /// SHA-256 fingerprint as colon separated hex bytes.
+    pub sha256: String,
+}
+
+/// Facts about the transfer, used by --write-out and --report.
+#[derive(Clone)]
+pub struct Metrics {
+    pub start: Instant,
+    pub url: String,
+    pub url_effective: String,
+    pub request_method: String,
+    pub request_headers: Vec<(String, String)>,
+    pub redirects: Vec<Redirect>,
+    pub num_redirects: usize,
+    pub http_code: u16,
+    pub http_version: String,
+    pub response_headers: Vec<(String, String)>,
+    pub content_type: String,
+    pub remote_ip: String,
+    pub remote_port: u16,
+    /// TLS session of the last connection (recorded with rustls only, see certinfo).
+    pub tls_version: String,
+    pub tls_cipher: String,
+    pub alpn: String,
+    pub certs: Vec<PeerCertificate>,
+    pub size_download: u64,
+    pub size_written: u64,
+    pub filename_effective: String,
+    pub time_starttransfer: f64,
+    pub md5: String,
+    pub sha256: String,
+    pub verified: Option<bool>,
+    pub exitcode: i32,
+    pub errormsg: String,
+    pub error_type: Option<ErrorType>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Metrics {
+            start: Instant::now(),
+            url: String::new(),
+            url_effective: String::new(),
+            request_method: String::new(),
+            request_headers: Vec::new(),
+            redirects: Vec::new(),
+            num_redirects: 0,
+            http_code: 0,
+            http_version: String::new(),
+            response_headers: Vec::new(),
+            content_type: String::new(),
+            remote_ip: String::new(),
+            remote_port: 0,
+            tls_version: String::new(),
+            tls_cipher: String::new(),
+            alpn: String::new(),
+            certs: Vec::new(),
+            size_download: 0,
+            size_written: 0,
+            filename_effective: String::new(),
+            time_starttransfer: 0.0,
+            md5: String::new(),
+            sha256: String::new(),
+            verified: None,
+            exitcode: 0,
+            errormsg: String::new(),
+            error_type: None,
+        }
+    }
+
+    /// Time elapsed since start of the transfer in seconds.
+    pub fn time_total(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// Average download speed in bytes per second.
+    pub fn speed_download(&self) -> u64 {
+        let time_total = self.time_total();
+        if time_total > 0.0 { (self.size_download as f64 / time_total) as u64 } else { 0 }
+    }
+}
+
+static METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(|| Mutex::new(Metrics::new()));
+
+/// Starts the measurement of the transfer.
+pub fn init_metrics(url: &str) {
+    update_metrics(|metrics| {
+        metrics.start = Instant::now();
+        metrics.url = String::from(url);
+        metrics.url_effective = String::from(url);
+    });
+}
+
+pub fn update_metrics<F: FnOnce(&mut Metrics)>(update: F) {
+    let mut metrics = METRICS.lock().unwrap_or_else(|err| err.into_inner());
+    update(&mut metrics);
+}
+
+/// Records the category of the error which is about to terminate the transfer.
+pub fn set_error_type(error_type: ErrorType) {
+    update_metrics(|metrics| metrics.error_type = Some(error_type));
+}
+
+/// Converts headers to name-value pairs; values which are not valid UTF-8 are converted lossy.
+pub fn get_header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter()
+        .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+        .collect()
+}
+
+pub fn get_metrics() -> Metrics {
+    METRICS.lock().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+/// Formats the HTTP version as used by %{http_version}, e.g. "1.1" or "2".
+pub fn format_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_11 => "1.1",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "",
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+/// Credentials of a machine entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Machine {
+    pub login: String,
+    pub password: String,
+}
+
+/// Contents of a .netrc file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Netrc {
+    machines: Vec<(String, Machine)>,
+    default: Option<Machine>,
+}
+
+impl Netrc {
+    /// Reads a netrc file.
+    pub fn from_file(filename: &PathBuf) -> Result<Netrc, String> {
+        let text = std::fs::read_to_string(filename)
+            .map_err(|_| format!("failed to read netrc file: {}", filename.display()))?;
+        Ok(Netrc::parse(&text))
+    }
+
+    /// Returns the default netrc file (~/.netrc).
+    pub fn default_file() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc"))
+    }
+
+    /// Parses the contents of a netrc file.
+    /// Macro definitions (macdef) are skipped, tokens may be quoted.
+    pub fn parse(text: &str) -> Netrc {
+        let mut netrc = Netrc::default();
+        let mut tokens = Tokenizer { chars: text.chars().peekable() };
+
+        // host of the current entry, None for the default entry
+        let mut current: Option<(Option<String>, Machine)> = None;
+        while let Some(token) = tokens.next_token() {
+            match token.as_str() {
+                "machine" | "default" => {
+                    netrc.add(current.take());
+                    let host = if token == "machine" {
+                        match tokens.next_token() {
+                            Some(host) => Some(host),
+                            None => { break; }
+                        }
+                    }
+                    else {
+                        None
+                    };
+                    current = Some((host, Machine { login: String::new(), password: String::new() }));
+                },
+                "login" => {
+                    let value = tokens.next_token().unwrap_or_default();
+                    if let Some((_, machine)) = &mut current {
+                        machine.login = value;
+                    }
+                },
+                "password" => {
+                    let value = tokens.next_token().unwrap_or_default();
+                    if let Some((_, machine)) = &mut current {
+                        machine.password = value;
+                    }
+                },
+                "account" => {
+                    tokens.next_token();
+                },
+                "macdef" => {
+                    tokens.next_token();
+                    tokens.skip_macro();
+                },
+                _ => {}
+            }
+        }
+        netrc.add(current);
+        netrc
+    }
+
+    fn add(&mut self, entry: Option<(Option<String>, Machine)>) {
+        match entry {
+            Some((Some(host), machine)) => { self.machines.push((host, machine)); },
+            Some((None, machine)) if self.default.is_none() => { self.default = Some(machine); },
+            _ => {}
+        }
+    }
+
+    /// Returns the credentials of a host; the first matching entry is used,
+    /// the default entry applies to all other hosts.
+    pub fn get(&self, host: &str) -> Option<&Machine> {
+        self.machines.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .map(|(_, machine)| machine)
+            .or(self.default.as_ref())
+    }
+}
+
+struct Tokenizer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Tokenizer<'_> {
+    /// Returns the next token; tokens are separated by whitespace,
+    /// quoted tokens may contain whitespace and backslash escapes.
+    fn next_token(&mut self) -> Option<String> {
+        loop {
+            while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+            // comment
+            if self.chars.peek() == Some(&'#') {
+                while self.chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            break;
+        }
+
+        let mut token = String::new();
+        if self.chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = self.chars.next() {
+                match c {
+                    '"' => { break; },
+                    '\\' => {
+                        match self.chars.next() {
+                            Some('n') => { token.push('\n'); },
+                            Some('r') => { token.push('\r'); },
+                            Some('t') => { token.push('\t'); },
+                            Some(c) => { token.push(c); },
+                            None => {}
+                        }
+                    },
+                    _ => { token.push(c); }
+                }
+            }
+            return Some(token);
+        }
+
+        while let Some(c) = self.chars.next_if(|c| !c.is_whitespace()) {
+            token.push(c);
+        }
+        if token.is_empty() { None } else { Some(token) }
+    }
+
+    /// Skips the body of a macro definition, which ends with an empty line.
+    fn skip_macro(&mut self) {
+        // rest of the macdef line
+        while self.chars.next_if(|c| *c != '\n').is_some() {}
+        self.chars.next();
+
+        loop {
+            match self.chars.peek() {
+                None => { break; },
+                Some('\n') => { self.chars.next(); break; },
+                Some('\r') => {
+                    self.chars.next();
+                    if self.chars.next_if_eq(&'\n').is_some() {
+                        break;
+                    }
+                },
+                Some(_) => {
+                    while self.chars.next_if(|c| *c != '\n').is_some() {}
+                    self.chars.next();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(login: &str, password: &str) -> Machine {
+        Machine { login: String::from(login), password: String::from(password) }
+    }
+
+    #[test]
+    fn test_parse() {
+        let netrc = Netrc::parse(r#"
+# artifact server
+machine example.com login user password secret
+macdef init
+machine evil.com login evil password evil
+
+machine other.org
+    login "john doe"
+    password "pass word \"quoted\""
+    account ignored
+default login anonymous password guest
+"#);
+
+        assert_eq!(Some(&machine("user", "secret")), netrc.get("example.com"));
+        assert_eq!(Some(&machine("user", "secret")), netrc.get("EXAMPLE.com"));
+        assert_eq!(Some(&machine("john doe", "pass word \"quoted\"")), netrc.get("other.org"));
+        assert_eq!(Some(&machine("anonymous", "guest")), netrc.get("evil.com"));
+    }
+
+    #[test]
+    fn test_parse_without_default() {
+        let netrc = Netrc::parse("machine a login x password y machine b login z");
+        assert_eq!(Some(&machine("x", "y")), netrc.get("a"));
+        assert_eq!(Some(&machine("z", "")), netrc.get("b"));
+        assert_eq!(None, netrc.get("c"));
+    }
+}
@@ -0,0 +1,307 @@
+use rustls::pki_types::CertificateDer;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use x509_parser::asn1_rs::{Any, BitString, Class, FromDer, Oid, Tag};
+use x509_parser::oid_registry::{OID_HASH_SHA1, OID_NIST_HASH_SHA256};
+use x509_parser::prelude::{AlgorithmIdentifier, X509Certificate, X509Name};
+use x509_parser::time::ASN1Time;
+use x509_parser::verify::verify_signature as verify_raw_signature;
+
+/// Tolerated clock difference between fetch and the OCSP responder (in seconds).
+const MAX_CLOCK_SKEW: i64 = 300;
+
+/// Response type of basic OCSP responses (id-pkix-ocsp-basic).
+const OCSP_BASIC: &str = "1.3.6.1.5.5.7.48.1.1";
+
+/// BasicOCSPResponse (RFC 6960), referencing the DER encoded response.
+struct BasicResponse<'a> {
+    /// DER encoded ResponseData, which is signed by the responder.
+    tbs_response_data: &'a [u8],
+    responder_id: ResponderId<'a>,
+    responses: Vec<SingleResponse<'a>>,
+    signature_algorithm: AlgorithmIdentifier<'a>,
+    signature: BitString<'a>,
+    /// Certificates sent by the responder, e.g. a delegated responder.
+    certs: Vec<X509Certificate<'a>>,
+}
+
+enum ResponderId<'a> {
+    ByName(X509Name<'a>),
+    /// SHA-1 hash of the public key of the responder.
+    ByKey(&'a [u8]),
+}
+
+struct SingleResponse<'a> {
+    hash_algorithm: Oid<'a>,
+    issuer_name_hash: &'a [u8],
+    issuer_key_hash: &'a [u8],
+    serial_number: &'a [u8],
+    status: CertStatus,
+    this_update: ASN1Time,
+    next_update: Option<ASN1Time>,
+}
+
+#[derive(Debug, PartialEq)]
+enum CertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// Verifies the OCSP response stapled by the server (--cert-status).
+///
+/// The response has to be signed by the issuer of the server certificate (or by
+/// a responder delegated by the issuer), has to be current and has to report the
+/// server certificate as good. The issuer is looked up in the certificates sent
+/// by the server and the CA certificates of --cacert and --capath; only a
+/// certificate whose key signed the server certificate is accepted, since names
+/// and key identifiers of certificates sent by the server can be forged.
+pub fn verify_stapled_response(
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    ca_certs: &[CertificateDer<'_>],
+    response: &[u8],
+) -> Result<(), String> {
+    if response.is_empty() {
+        return Err(String::from("no OCSP response stapled by the server"));
+    }
+    let (_, cert) = X509Certificate::from_der(end_entity)
+        .map_err(|_| String::from("failed to parse the server certificate"))?;
+    let chain: Vec<X509Certificate> = intermediates.iter().chain(ca_certs)
+        .filter_map(|der| X509Certificate::from_der(der).ok().map(|(_, cert)| cert))
+        .collect();
+    let issuer = chain.iter()
+        .chain(std::iter::once(&cert))
+        .find(|issuer| cert.issuer().as_raw() == issuer.subject().as_raw() && is_signed_by(&cert, issuer))
+        .ok_or_else(|| String::from("issuer of the server certificate not found (required by --cert-status)"))?;
+
+    let (status, basic) = parse_response(response)
+        .ok_or_else(|| String::from("invalid OCSP response"))?;
+    if status != 0 {
+        return Err(format!("OCSP responder returned an error (status {})", status));
+    }
+    let basic = basic.and_then(parse_basic_response)
+        .ok_or_else(|| String::from("invalid OCSP response"))?;
+    if !verify_signature(&basic, issuer) {
+        return Err(String::from("invalid signature of OCSP response"));
+    }
+
+    let status = find_status(&basic, &cert, issuer)
+        .ok_or_else(|| String::from("OCSP response does not contain the status of the server certificate"))?;
+    if !is_current(status) {
+        return Err(String::from("OCSP response is outdated"));
+    }
+    match status.status {
+        CertStatus::Good => Ok(()),
+        CertStatus::Revoked => Err(String::from("server certificate is revoked (OCSP)")),
+        CertStatus::Unknown => Err(String::from("status of the server certificate is unknown (OCSP)")),
+    }
+}
+
+/// Returns true, if the certificate is signed by the key of the issuer.
+fn is_signed_by(cert: &X509Certificate, issuer: &X509Certificate) -> bool {
+    cert.verify_signature(Some(issuer.public_key())).is_ok()
+}
+
+/// Verifies the signature of the response, which has to be created by the issuer
+/// or by a certificate of the issuer allowed to sign OCSP responses.
+///
+/// The signer is looked up in the issuer and the certificates included in the
+/// response (delegated responders) by the responder ID.
+fn verify_signature(basic: &BasicResponse, issuer: &X509Certificate) -> bool {
+    let signer = std::iter::once(issuer).chain(&basic.certs)
+        .find(|cert| match &basic.responder_id {
+            ResponderId::ByName(name) => cert.subject().as_raw() == name.as_raw(),
+            ResponderId::ByKey(hash) => Sha1::digest(&cert.public_key().subject_public_key.data).as_slice() == *hash,
+        });
+    let Some(signer) = signer else {
+        return false;
+    };
+    if verify_raw_signature(signer.public_key(), &basic.signature_algorithm, &basic.signature, basic.tbs_response_data).is_err() {
+        return false;
+    }
+
+    let is_delegated = || {
+        let ocsp_signing = signer.extended_key_usage().ok().flatten()
+            .is_some_and(|extension| extension.value.ocsp_signing);
+        ocsp_signing && signer.issuer().as_raw() == issuer.subject().as_raw() && is_signed_by(signer, issuer)
+    };
+    signer == issuer || is_delegated()
+}
+
+/// Returns the status of the certificate; responders identify certificates by
+/// SHA-1 (usually) or SHA-256 hashes of the issuer.
+fn find_status<'a>(basic: &'a BasicResponse, cert: &X509Certificate, issuer: &X509Certificate) -> Option<&'a SingleResponse<'a>> {
+    let name = issuer.subject().as_raw();
+    let key = &issuer.public_key().subject_public_key.data;
+    basic.responses.iter().find(|response| {
+        let (name_hash, key_hash) = if response.hash_algorithm == OID_HASH_SHA1 {
+            (Sha1::digest(name).to_vec(), Sha1::digest(key).to_vec())
+        }
+        else if response.hash_algorithm == OID_NIST_HASH_SHA256 {
+            (Sha256::digest(name).to_vec(), Sha256::digest(key).to_vec())
+        }
+        else {
+            return false;
+        };
+        response.issuer_name_hash == name_hash
+            && response.issuer_key_hash == key_hash
+            && response.serial_number == cert.raw_serial()
+    })
+}
+
+/// Returns true, if the current time is within the validity period of the status
+/// (thisUpdate and nextUpdate, if present), taking the clock skew into account.
+fn is_current(response: &SingleResponse) -> bool {
+    let now = ASN1Time::now().timestamp();
+    let this_update = response.this_update.timestamp();
+    this_update <= now + MAX_CLOCK_SKEW && response.next_update.is_none_or(|next_update| {
+        let next_update = next_update.timestamp();
+        this_update <= next_update && now - MAX_CLOCK_SKEW <= next_update
+    })
+}
+
+/// Reads the next DER encoded element of a sequence.
+fn next<'a>(data: &mut &'a [u8]) -> Option<Any<'a>> {
+    let (rest, any) = Any::from_der(data).ok()?;
+    *data = rest;
+    Some(any)
+}
+
+/// Reads the next element, if it is the context specific tag (optional elements).
+fn next_tagged<'a>(data: &mut &'a [u8], tag: u32) -> Option<Any<'a>> {
+    let (rest, any) = Any::from_der(data).ok()?;
+    if any.class() != Class::ContextSpecific || any.tag() != Tag(tag) {
+        return None;
+    }
+    *data = rest;
+    Some(any)
+}
+
+/// Reads the next element, which has to be of the given type.
+fn next_of<'a>(data: &mut &'a [u8], tag: Tag) -> Option<&'a [u8]> {
+    next(data).filter(|any| any.class() == Class::Universal && any.tag() == tag).map(|any| any.data)
+}
+
+fn parse_der<'a, T: FromDer<'a, E>, E>(data: &mut &'a [u8]) -> Option<T> {
+    let (rest, value) = T::from_der(data).ok()?;
+    *data = rest;
+    Some(value)
+}
+
+/// Parses an OCSPResponse; returns the response status and the BasicOCSPResponse
+/// (if successful).
+fn parse_response(mut data: &[u8]) -> Option<(u32, Option<&[u8]>)> {
+    let mut response = next_of(&mut data, Tag::Sequence)?;
+    let status = next_of(&mut response, Tag::Enumerated)?;
+    let status = match status {
+        [status] => u32::from(*status),
+        _ => return None,
+    };
+    let basic = next_tagged(&mut response, 0).and_then(|bytes| {
+        let mut bytes = next_of(&mut &bytes.data[..], Tag::Sequence)?;
+        let response_type = parse_der::<Oid, _>(&mut bytes)?;
+        (response_type.to_id_string() == OCSP_BASIC).then_some(())?;
+        next_of(&mut bytes, Tag::OctetString)
+    });
+    Some((status, basic))
+}
+
+fn parse_basic_response(mut data: &[u8]) -> Option<BasicResponse<'_>> {
+    let mut basic = next_of(&mut data, Tag::Sequence)?;
+    let tbs_start = basic;
+    let mut tbs = next_of(&mut basic, Tag::Sequence)?;
+    let tbs_response_data = &tbs_start[..tbs_start.len() - basic.len()];
+    let signature_algorithm = parse_der::<AlgorithmIdentifier, _>(&mut basic)?;
+    let signature = parse_der::<BitString, _>(&mut basic)?;
+    let mut certs = Vec::new();
+    if let Some(tagged) = next_tagged(&mut basic, 0) {
+        let mut sequence = next_of(&mut &tagged.data[..], Tag::Sequence)?;
+        while !sequence.is_empty() {
+            certs.push(parse_der::<X509Certificate, _>(&mut sequence)?);
+        }
+    }
+
+    // ResponseData: version (optional), responderID, producedAt, responses, extensions
+    let _version = next_tagged(&mut tbs, 0);
+    let responder_id = match next(&mut tbs)? {
+        any if any.class() == Class::ContextSpecific && any.tag() == Tag(1) =>
+            ResponderId::ByName(X509Name::from_der(any.data).ok()?.1),
+        any if any.class() == Class::ContextSpecific && any.tag() == Tag(2) =>
+            ResponderId::ByKey(next_of(&mut &any.data[..], Tag::OctetString)?),
+        _ => return None,
+    };
+    let _produced_at = next_of(&mut tbs, Tag::GeneralizedTime)?;
+    let mut sequence = next_of(&mut tbs, Tag::Sequence)?;
+    let mut responses = Vec::new();
+    while !sequence.is_empty() {
+        responses.push(parse_single_response(next_of(&mut sequence, Tag::Sequence)?)?);
+    }
+
+    Some(BasicResponse { tbs_response_data, responder_id, responses, signature_algorithm, signature, certs })
+}
+
+fn parse_single_response(mut data: &[u8]) -> Option<SingleResponse<'_>> {
+    let mut cert_id = next_of(&mut data, Tag::Sequence)?;
+    let hash_algorithm = parse_der::<AlgorithmIdentifier, _>(&mut cert_id)?.algorithm;
+    let issuer_name_hash = next_of(&mut cert_id, Tag::OctetString)?;
+    let issuer_key_hash = next_of(&mut cert_id, Tag::OctetString)?;
+    let serial_number = next_of(&mut cert_id, Tag::Integer)?;
+
+    let status = next(&mut data).filter(|any| any.class() == Class::ContextSpecific)?;
+    let status = match status.tag() {
+        Tag(0) => CertStatus::Good,
+        Tag(1) => CertStatus::Revoked,
+        _ => CertStatus::Unknown,
+    };
+    let this_update = parse_der::<ASN1Time, _>(&mut data)?;
+    let next_update = match next_tagged(&mut data, 0) {
+        Some(tagged) => Some(ASN1Time::from_der(tagged.data).ok()?.1),
+        None => None,
+    };
+
+    Some(SingleResponse { hash_algorithm, issuer_name_hash, issuer_key_hash, serial_number, status, this_update, next_update })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::pem::PemObject;
+
+    fn verify(response: &[u8]) -> Result<(), String> {
+        let cert = CertificateDer::from_pem_slice(include_bytes!("../test-utils/test-server/src/cert.pem")).unwrap();
+        verify_stapled_response(&cert, &[], &[], response)
+    }
+
+    #[test]
+    fn test_verify_stapled_response() {
+        assert_eq!(Ok(()), verify(include_bytes!("../test-utils/test-server/src/ocsp-good.der")));
+        assert_eq!(Err(String::from("server certificate is revoked (OCSP)")),
+            verify(include_bytes!("../test-utils/test-server/src/ocsp-revoked.der")));
+        assert_eq!(Err(String::from("invalid signature of OCSP response")),
+            verify(include_bytes!("../test/certs/ocsp-forged.der")));
+        assert_eq!(Err(String::from("no OCSP response stapled by the server")), verify(&[]));
+        assert_eq!(Err(String::from("invalid OCSP response")), verify(b"garbage"));
+    }
+
+    #[test]
+    fn test_verify_stapled_response_forged_issuer() {
+        // same name and key identifier as the issuer of the server certificate, but another key
+        let cert = CertificateDer::from_pem_slice(include_bytes!("../test-utils/test-server/src/cert.pem")).unwrap();
+        let forged = CertificateDer::from_pem_slice(include_bytes!("../test/certs/ocsp-forged-issuer.pem")).unwrap();
+        let intermediates = [forged];
+        assert!(verify_stapled_response(&cert, &intermediates, &[], include_bytes!("../test/certs/ocsp-forged-issuer.der")).is_err());
+        assert_eq!(Ok(()), verify_stapled_response(&cert, &intermediates, &[], include_bytes!("../test-utils/test-server/src/ocsp-good.der")));
+    }
+
+    #[test]
+    fn test_verify_stapled_response_unknown_issuer() {
+        let cert = CertificateDer::from_pem_slice(include_bytes!("../test-utils/proxy-server/src/cert.pem")).unwrap();
+        let response = include_bytes!("../test-utils/test-server/src/ocsp-good.der");
+        assert!(verify_stapled_response(&cert, &[], &[], response).unwrap_err().contains("issuer"));
+
+        // the status of another certificate does not apply
+        let ca = CertificateDer::from_pem_slice(include_bytes!("../test-utils/proxy-server/src/proxy-ca.pem")).unwrap();
+        assert!(verify_stapled_response(&cert, &[], &[ca], response).is_err());
+    }
+}
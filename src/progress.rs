@@ -0,0 +1,178 @@
+use std::io::{stderr, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::args::Args;
+
+/// Minimum time between two updates of the progress display.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Width of the compact progress bar (-#) in characters.
+const BAR_WIDTH: usize = 50;
+
+#[derive(PartialEq)]
+enum Style {
+    Off,
+    Meter,
+    Bar,
+}
+
+/// Displays the progress of a download on stderr.
+pub struct Progress {
+    style: Style,
+    total: Option<u64>,
+    count: u64,
+    start: Instant,
+    last_update: Option<Instant>,
+    last_width: usize,
+}
+
+impl Progress {
+    /// Creates the progress display.
+    /// It is disabled in silent mode, with --no-progress-meter
+    /// and if stderr is not a terminal.
+    pub fn new(args: &Args, total: Option<u64>) -> Self {
+        let style = if args.silent || args.no_progress_meter || !stderr().is_terminal() {
+            Style::Off
+        }
+        else if args.progress_bar {
+            Style::Bar
+        }
+        else {
+            Style::Meter
+        };
+
+        Progress {
+            style,
+            total: total.filter(|total| *total > 0),
+            count: 0,
+            start: Instant::now(),
+            last_update: None,
+            last_width: 0,
+        }
+    }
+
+    /// Accounts for count received bytes and updates the display.
+    pub fn update(&mut self, count: usize) {
+        self.count += count as u64;
+
+        let now = Instant::now();
+        let is_due = self.last_update
+            .map(|last_update| now.duration_since(last_update) >= UPDATE_INTERVAL)
+            .unwrap_or(true);
+        if is_due {
+            self.last_update = Some(now);
+            self.draw();
+        }
+    }
+
+    /// Draws the final state and terminates the progress line.
+    pub fn finish(&mut self) {
+        if self.style != Style::Off {
+            self.draw();
+            eprintln!();
+            self.style = Style::Off;
+        }
+    }
+
+    fn draw(&mut self) {
+        let line = match self.style {
+            Style::Off => { return; },
+            Style::Meter => self.meter(),
+            Style::Bar => self.bar(),
+        };
+
+        // pad with spaces to overwrite the remains of a longer previous line
+        let width = line.chars().count();
+        let padding = self.last_width.saturating_sub(width);
+        self.last_width = width;
+
+        let mut stderr = stderr();
+        let _ = write!(stderr, "\r{}{}", line, " ".repeat(padding));
+        let _ = stderr.flush();
+    }
+
+    fn rate(&self) -> f64 {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        if elapsed > 0.0 { self.count as f64 / elapsed } else { 0.0 }
+    }
+
+    fn meter(&self) -> String {
+        let rate = self.rate();
+        let elapsed = format_duration(self.start.elapsed().as_secs());
+        match self.total {
+            Some(total) => {
+                let percent = (self.count as f64 * 100.0 / total as f64).min(100.0);
+                let eta = if rate > 0.0 {
+                    format_duration((total.saturating_sub(self.count) as f64 / rate) as u64)
+                }
+                else {
+                    String::from("--:--:--")
+                };
+                format!("{:5.1}% {:>7} / {:>7} {:>7}/s  {} elapsed  ETA {}",
+                    percent, format_bytes(self.count), format_bytes(total), format_bytes(rate as u64),
+                    elapsed, eta)
+            },
+            None => {
+                format!("{:>7} {:>7}/s  {} elapsed",
+                    format_bytes(self.count), format_bytes(rate as u64), elapsed)
+            }
+        }
+    }
+
+    fn bar(&self) -> String {
+        match self.total {
+            Some(total) => {
+                let fraction = (self.count as f64 / total as f64).min(1.0);
+                let filled = (fraction * BAR_WIDTH as f64) as usize;
+                format!("{}{} {:5.1}%", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled), fraction * 100.0)
+            },
+            None => {
+                format!("{} {:>7}", "#".repeat(((self.count / 65536) as usize) % (BAR_WIDTH + 1)), format_bytes(self.count))
+            }
+        }
+    }
+}
+
+/// Formats a number of bytes using binary units (k, M, G, T).
+fn format_bytes(count: u64) -> String {
+    const UNITS: [&str; 4] = ["k", "M", "G", "T"];
+
+    if count < 1024 {
+        return format!("{}", count);
+    }
+
+    let mut value = count as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// Formats a number of seconds as hh:mm:ss.
+fn format_duration(seconds: u64) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!("0", format_bytes(0));
+        assert_eq!("1023", format_bytes(1023));
+        assert_eq!("1.0k", format_bytes(1024));
+        assert_eq!("1.5k", format_bytes(1536));
+        assert_eq!("2.0M", format_bytes(2 * 1024 * 1024));
+        assert_eq!("3.0G", format_bytes(3 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!("00:00:00", format_duration(0));
+        assert_eq!("00:01:05", format_duration(65));
+        assert_eq!("02:00:01", format_duration(7201));
+    }
+}
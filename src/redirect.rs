@@ -0,0 +1,122 @@
+use reqwest::{header, Method, StatusCode, Url};
+use reqwest::header::HeaderMap;
+
+/// Headers which are removed when a redirect leaves the origin of the request,
+/// including headers passed by -H (other custom headers are sent to every host).
+const SENSITIVE_HEADERS: [header::HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::HeaderName::from_static("cookie2"),
+    header::PROXY_AUTHORIZATION,
+];
+
+/// Returns true, if the status code requests to follow the Location header.
+pub fn is_redirect(status: StatusCode) -> bool {
+    matches!(status,
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::SEE_OTHER
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT)
+}
+
+/// Returns the method used to follow a redirect and whether the request body is sent again.
+///
+/// 301, 302 and 303 switch to GET (unless the method is GET or HEAD already),
+/// 307 and 308 keep method and body. POST requests keep method and body on
+/// 301, 302 and 303, if keep_post is set (see --post301, --post302, --post303).
+pub fn get_redirect_method(status: StatusCode, method: &Method, keep_post: bool) -> (Method, bool) {
+    match status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER if keep_post && *method == Method::POST => {
+            (Method::POST, true)
+        },
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => {
+            if *method == Method::GET || *method == Method::HEAD {
+                (method.clone(), false)
+            }
+            else {
+                (Method::GET, false)
+            }
+        },
+        _ => (method.clone(), true),
+    }
+}
+
+/// Returns true, if two URLs differ in scheme, host or port.
+pub fn is_cross_origin(from: &Url, to: &Url) -> bool {
+    from.scheme() != to.scheme()
+        || from.host_str() != to.host_str()
+        || from.port_or_known_default() != to.port_or_known_default()
+}
+
+/// Returns true, if redirects to the host are allowed by the patterns of --redirect-allow-host.
+/// A pattern is either a host name or "*." followed by a domain, which matches
+/// all subdomains of the domain. All hosts are allowed, if no pattern is specified.
+pub fn is_allowed_host(host: &str, patterns: &[String]) -> bool {
+    if patterns.is_empty() {
+        return true;
+    }
+
+    let host = host.to_ascii_lowercase();
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.')),
+            None => host == pattern,
+        }
+    })
+}
+
+/// Removes headers carrying credentials.
+pub fn remove_sensitive_headers(headers: &mut HeaderMap) {
+    for name in &SENSITIVE_HEADERS {
+        headers.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_redirect_method() {
+        assert_eq!((Method::GET, false), get_redirect_method(StatusCode::FOUND, &Method::POST, false));
+        assert_eq!((Method::GET, false), get_redirect_method(StatusCode::SEE_OTHER, &Method::PUT, false));
+        assert_eq!((Method::HEAD, false), get_redirect_method(StatusCode::MOVED_PERMANENTLY, &Method::HEAD, false));
+        assert_eq!((Method::POST, true), get_redirect_method(StatusCode::TEMPORARY_REDIRECT, &Method::POST, false));
+        assert_eq!((Method::PUT, true), get_redirect_method(StatusCode::PERMANENT_REDIRECT, &Method::PUT, false));
+        assert_eq!((Method::POST, true), get_redirect_method(StatusCode::SEE_OTHER, &Method::POST, true));
+        assert_eq!((Method::GET, false), get_redirect_method(StatusCode::SEE_OTHER, &Method::PUT, true));
+    }
+
+    #[test]
+    fn test_is_allowed_host() {
+        let patterns = vec![String::from("github.com"), String::from("*.githubusercontent.com")];
+        assert!(is_allowed_host("GitHub.com", &patterns));
+        assert!(is_allowed_host("objects.githubusercontent.com", &patterns));
+        assert!(!is_allowed_host("githubusercontent.com", &patterns));
+        assert!(!is_allowed_host("evilgithubusercontent.com", &patterns));
+        assert!(!is_allowed_host("api.github.com", &patterns));
+        assert!(is_allowed_host("example.com", &[]));
+    }
+
+    #[test]
+    fn test_remove_sensitive_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, header::HeaderValue::from_static("Bearer secret"));
+        headers.insert(header::COOKIE, header::HeaderValue::from_static("session=secret"));
+        headers.insert(header::ACCEPT, header::HeaderValue::from_static("*/*"));
+        remove_sensitive_headers(&mut headers);
+        assert_eq!(vec![&header::ACCEPT], headers.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_is_cross_origin() {
+        let url = Url::parse("https://example.com/foo").unwrap();
+        assert!(!is_cross_origin(&url, &Url::parse("https://example.com/bar").unwrap()));
+        assert!(!is_cross_origin(&url, &Url::parse("https://example.com:443/bar").unwrap()));
+        assert!(is_cross_origin(&url, &Url::parse("http://example.com/foo").unwrap()));
+        assert!(is_cross_origin(&url, &Url::parse("https://example.org/foo").unwrap()));
+        assert!(is_cross_origin(&url, &Url::parse("https://example.com:8443/foo").unwrap()));
+    }
+}
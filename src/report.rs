@@ -0,0 +1,184 @@
+use serde_json::{json, Value};
+use std::io::{stderr, Write};
+use std::sync::OnceLock;
+
+use crate::args::Args;
+use crate::metrics::{get_metrics, Metrics};
+
+/// Headers whose values are not included in the report.
+pub const SENSITIVE_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+struct Report {
+    filename: Option<String>,
+    md5: Option<String>,
+    sha256: Option<String>,
+}
+
+static REPORT: OnceLock<Report> = OnceLock::new();
+
+/// Parses --report; supported values are "json" (written to stderr) and "json=FILE".
+pub fn init_report(args: &Args) -> Result<(), String> {
+    let value = match &args.report {
+        None => { return Ok(()); },
+        Some(value) => value,
+    };
+
+    let filename = match value.as_str() {
+        "json" => None,
+        _ => match value.strip_prefix("json=") {
+            Some(filename) if !filename.is_empty() => Some(String::from(filename)),
+            _ => { return Err(format!("unsupported report format: {}", value)); }
+        }
+    };
+
+    let _ = REPORT.set(Report {
+        filename,
+        md5: args.md5.as_ref().map(|value| value.to_lowercase()),
+        sha256: args.sha256.as_ref().map(|value| value.to_lowercase()),
+    });
+    Ok(())
+}
+
+/// Writes the report (if specified).
+pub fn write_report(exit_code: i32) {
+    if let Some(report) = REPORT.get() {
+        let document = create_report(report, &get_metrics(), exit_code);
+        let text = serde_json::to_string_pretty(&document).unwrap_or_default();
+
+        match &report.filename {
+            Some(filename) => {
+                if std::fs::write(filename, text + "\n").is_err() {
+                    eprintln!("error: failed to write report: {}", filename);
+                }
+            },
+            None => {
+                let _ = writeln!(stderr(), "{}", text);
+            }
+        }
+    }
+}
+
+fn headers_to_json(headers: &[(String, String)]) -> Value {
+    headers.iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.to_lowercase().as_str()) {
+                "[redacted]"
+            }
+            else {
+                value.as_str()
+            };
+            json!({ "name": name, "value": value })
+        })
+        .collect()
+}
+
+fn verification_to_json(expected: &Option<String>, actual: &str) -> Value {
+    match expected {
+        Some(expected) => json!({
+            "expected": expected,
+            "actual": actual,
+            "ok": !actual.is_empty() && expected == actual,
+        }),
+        None => Value::Null,
+    }
+}
+
+fn create_report(report: &Report, metrics: &Metrics, exit_code: i32) -> Value {
+    let response = if metrics.http_code > 0 {
+        json!({
+            "status": metrics.http_code,
+            "http_version": metrics.http_version,
+            "headers": headers_to_json(&metrics.response_headers),
+            "remote_ip": metrics.remote_ip,
+            "remote_port": metrics.remote_port,
+        })
+    }
+    else {
+        Value::Null
+    };
+
+    let redirects: Vec<Value> = metrics.redirects.iter()
+        .map(|redirect| json!({
+            "status": redirect.status,
+            "url": redirect.url,
+            "location": redirect.location,
+        }))
+        .collect();
+
+    let error = if exit_code != 0 {
+        json!({
+            "type": metrics.error_type.map(|error_type| error_type.as_str()).unwrap_or("other"),
+            "message": metrics.errormsg,
+        })
+    }
+    else {
+        Value::Null
+    };
+
+    json!({
+        "request": {
+            "method": metrics.request_method,
+            "url": metrics.url,
+            "headers": headers_to_json(&metrics.request_headers),
+        },
+        "url_effective": metrics.url_effective,
+        "redirects": redirects,
+        "response": response,
+        "bytes": {
+            "received": metrics.size_download,
+            "written": metrics.size_written,
+        },
+        "digests": {
+            "md5": metrics.md5,
+            "sha256": metrics.sha256,
+        },
+        "verification": {
+            "md5": verification_to_json(&report.md5, &metrics.md5),
+            "sha256": verification_to_json(&report.sha256, &metrics.sha256),
+        },
+        "time": {
+            "starttransfer": metrics.time_starttransfer,
+            "total": metrics.time_total(),
+        },
+        "exit_code": exit_code,
+        "error": error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::ErrorType;
+
+    #[test]
+    fn test_create_report() {
+        let report = Report { filename: None, md5: None, sha256: Some(String::from("abc")) };
+        let mut metrics = get_metrics();
+        metrics.http_code = 200;
+        metrics.sha256 = String::from("def");
+        metrics.request_headers = vec![
+            (String::from("authorization"), String::from("Bearer secret")),
+            (String::from("user-agent"), String::from("fetch")),
+        ];
+        metrics.errormsg = String::from("SHA256 checksum mismatch");
+        metrics.error_type = Some(ErrorType::Checksum);
+
+        let document = create_report(&report, &metrics, 1);
+        assert_eq!(200, document["response"]["status"]);
+        assert_eq!("[redacted]", document["request"]["headers"][0]["value"]);
+        assert_eq!("fetch", document["request"]["headers"][1]["value"]);
+        assert_eq!(Value::Null, document["verification"]["md5"]);
+        assert_eq!(false, document["verification"]["sha256"]["ok"]);
+        assert_eq!("checksum", document["error"]["type"]);
+    }
+
+    #[test]
+    fn test_create_report_without_response() {
+        let report = Report { filename: None, md5: None, sha256: None };
+        let metrics = get_metrics();
+
+        let document = create_report(&report, &metrics, 0);
+        assert_eq!(Value::Null, document["response"]);
+        assert_eq!(Value::Null, document["error"]);
+    }
+}
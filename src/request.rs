@@ -0,0 +1,279 @@
+use log::{info, warn, error};
+use reqwest::{header, Client, Method, RequestBuilder, Response, StatusCode, Url, Version};
+use reqwest::multipart::Form;
+use tokio::fs::File as TokioFile;
+
+use crate::args::Args;
+use crate::auth::{add_auth, handle_challenge};
+use crate::conditional::add_conditions;
+use crate::decoder::ACCEPT_ENCODING;
+use crate::headers::HeaderOutput;
+use crate::metrics::{format_version, get_header_pairs, set_error_type, update_metrics, ErrorType, Redirect};
+use crate::resolve::with_target;
+use crate::redirect::{get_redirect_method, is_allowed_host, is_cross_origin, is_redirect, remove_sensitive_headers};
+use crate::throttle::{send_request, SpeedCheck};
+use crate::trace::{trace_info, trace_request, trace_response};
+use crate::tls::get_verify_error;
+use crate::certinfo::record_alpn;
+use crate::{exit, get_protocols, Protocols, EXIT_OPERATION_TIMEOUT};
+
+/// The final response of a request, after redirects were followed.
+pub struct Fetched {
+    pub response: Response,
+    /// Method of the last request (redirects may change the method).
+    pub method: Method,
+    /// True, if credentials must not be sent to the host of the response
+    /// (a redirect left the origin of the initial request, see --location-trusted).
+    pub strip_credentials: bool,
+}
+
+pub fn add_headers(mut request_builder: RequestBuilder, args: &Args) -> RequestBuilder {
+    // additional headers
+    for x in &args.header {
+        if let Some((name, value)) = x.split_once(':') {
+            let name = name.trim();
+            let value = value.trim();
+
+            request_builder = request_builder.header(name, value);
+        }
+    }
+
+    // user agent
+    if let Some(ref user_agent) = args.user_agent {
+        request_builder = request_builder.header(header::USER_AGENT, user_agent);
+    }
+
+    request_builder
+}
+
+/// Returns true, if the request to the URL is sent via HTTP/3 (--http3, --http3-only).
+/// reqwest uses HTTP/3 only for requests asking for it, which requires https.
+fn uses_http3(args: &Args, url: &Url) -> bool {
+    cfg!(feature = "http3") && (args.http3 || args.http3_only) && url.scheme() == "https"
+}
+
+async fn build_request(client: &Client, method: Method, url: Url, args: &Args, with_body: bool, http3: bool) -> RequestBuilder {
+    let mut request_builder = client.request(method, url);
+    if http3 {
+        request_builder = request_builder.version(Version::HTTP_3);
+    }
+    request_builder = add_headers(request_builder, args);
+    request_builder = add_conditions(request_builder);
+
+    // compression
+    if args.compressed {
+        request_builder = request_builder.header(header::ACCEPT_ENCODING, ACCEPT_ENCODING);
+    }
+
+    if !with_body {
+        return request_builder;
+    }
+
+    // data
+    if let Some(ref data) = args.data {
+        if let Some(filename) = data.strip_prefix('@') {
+            let file = TokioFile::open(filename).await;
+            if let Ok(file) = file {
+                request_builder = request_builder.body(file);
+            }
+            else {
+                warn!("failed to open file, this results in an empty request body");
+            }
+        } else {
+            request_builder = request_builder.body(data.clone());
+        }
+    }
+    else if let Some(ref data) = args.data_raw {
+        request_builder = request_builder.body(data.clone());
+    }
+    // multipart data
+    else if !args.form.is_empty() {
+        let mut form_data = Form::new();
+        for key_value_pair in &args.form {
+            if let Some((key, value)) = key_value_pair.split_once('=') {
+                let key = key.trim();
+
+                form_data = form_data.text(String::from(key), String::from(value));
+            }
+        }
+        request_builder = request_builder.multipart(form_data);
+    }
+
+    request_builder
+}
+
+fn record_response(response: &Response, args: &Args) {
+    update_metrics(|metrics| {
+        metrics.time_starttransfer = metrics.start.elapsed().as_secs_f64();
+        metrics.http_code = response.status().as_u16();
+        metrics.http_version = String::from(format_version(response.version()));
+        metrics.url_effective = response.url().to_string();
+        metrics.response_headers = get_header_pairs(response.headers());
+        metrics.content_type = response.headers().get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+            .unwrap_or_default();
+        if let Some(addr) = response.remote_addr() {
+            metrics.remote_ip = addr.ip().to_string();
+            metrics.remote_port = addr.port();
+        }
+        metrics.filename_effective = args.output.clone().unwrap_or_default();
+    });
+    record_alpn(response, args);
+}
+
+/// Resolves the Location header of a redirect response and checks, if the
+/// redirect may be followed (--proto, --proto-redir, --redirect-allow-host
+/// and --max-redirs).
+fn get_redirect_url(response: &Response, args: &Args, protocols: &Protocols, num_redirects: usize) -> Result<Url, String> {
+    let next_url = response.headers().get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| response.url().join(value).ok())
+        .ok_or_else(|| String::from("invalid redirect location"))?;
+
+    if !protocols.allows(&next_url) {
+        return Err(format!("protocol \"{}\" not allowed for redirects", next_url.scheme()));
+    }
+
+    let host = next_url.host_str().unwrap_or_default();
+    if !is_allowed_host(host, &args.redirect_allow_host) {
+        return Err(format!("redirect to host {} not allowed", host));
+    }
+
+    if args.max_redirs >= 0 && num_redirects >= args.max_redirs as usize {
+        return Err(format!("maximum ({}) redirects followed", args.max_redirs));
+    }
+
+    Ok(next_url)
+}
+
+/// Sends the request and follows redirects (if enabled).
+/// The headers of each response are passed to the header output.
+///
+/// Redirects are followed here instead of by a redirect policy of reqwest
+/// (the client is built with `Policy::none()`): a policy sees the status and
+/// the URLs only, but each intermediate response has to be written by -i/-D,
+/// recorded for --report and may carry a Digest challenge.
+///
+/// The URL must use a protocol allowed by --proto; redirect targets must use
+/// a protocol allowed by both --proto and --proto-redir.
+pub async fn fetch(client: &Client, args: &Args, method: Method, header_output: &mut HeaderOutput) -> Fetched {
+    let url = Url::parse(&args.url);
+    if let Err(err) = url {
+        set_error_type(ErrorType::Usage);
+        error!("invalid url: {}", err);
+        exit(1);
+    }
+    let mut url = url.unwrap();
+
+    let protocols = get_protocols(&args.proto);
+    let redirect_protocols = protocols.intersect(&get_protocols(&args.proto_redir));
+    if !protocols.allows(&url) {
+        set_error_type(ErrorType::Usage);
+        error!("protocol \"{}\" not supported or disabled", url.scheme());
+        exit(1);
+    }
+
+    let mut method = method;
+    let mut with_body = true;
+    let mut strip_credentials = false;
+    let mut challenged = false;
+    let mut num_redirects = 0;
+    let mut http3_failed = false;
+
+    loop {
+        if args.http3_only && url.scheme() != "https" {
+            set_error_type(ErrorType::Usage);
+            error!("--http3-only requires https: {}", url);
+            exit(1);
+        }
+        let http3 = uses_http3(args, &url) && !http3_failed;
+        let request = build_request(client, method.clone(), url.clone(), args, with_body, http3).await.build();
+        if let Err(err) = request {
+            set_error_type(ErrorType::Usage);
+            error!("{}", err);
+            exit(1);
+        }
+        let mut request = request.unwrap();
+        if strip_credentials {
+            remove_sensitive_headers(request.headers_mut());
+        }
+        add_auth(&mut request, !strip_credentials);
+        let has_credentials = request.headers().contains_key(header::AUTHORIZATION);
+        update_metrics(|metrics| {
+            metrics.request_method = request.method().to_string();
+            metrics.request_headers = get_header_pairs(request.headers());
+        });
+        trace_request(&request);
+
+        let response = send_request(with_target(&url, client.execute(request)), &SpeedCheck::new(args)).await;
+        if let Err(message) = response {
+            set_error_type(ErrorType::Timeout);
+            error!("{}", message);
+            exit(EXIT_OPERATION_TIMEOUT);
+        }
+        let response = response.unwrap();
+        if let (Err(_), Some((exit_code, message))) = (&response, get_verify_error(&url)) {
+            set_error_type(ErrorType::Tls);
+            error!("{}", message);
+            exit(exit_code);
+        }
+        // --http3 falls back to the TCP connection, if the QUIC connection fails
+        if let (true, true, Err(err)) = (http3, args.http3, &response) {
+            info!("HTTP/3 request failed, falling back to HTTP/2: {}", err);
+            http3_failed = true;
+            continue;
+        }
+        if let Err(err) = response {
+            set_error_type(ErrorType::from_reqwest(&err));
+            error!("{}", err);
+            exit(1);
+        }
+        let response = response.unwrap();
+        record_response(&response, args);
+        trace_response(&response);
+        header_output.write(&response);
+
+        // repeat the request once with credentials, if the server requests Digest authentication
+        if !challenged && handle_challenge(&response, has_credentials, !strip_credentials) {
+            challenged = true;
+            continue;
+        }
+
+        let status = response.status();
+        if !args.location || !is_redirect(status) || !response.headers().contains_key(header::LOCATION) {
+            return Fetched { response, method, strip_credentials };
+        }
+
+        let next_url = get_redirect_url(&response, args, &redirect_protocols, num_redirects);
+        if let Err(message) = next_url {
+            set_error_type(ErrorType::Redirect);
+            error!("{}", message);
+            exit(1);
+        }
+        let next_url = next_url.unwrap();
+        num_redirects += 1;
+        update_metrics(|metrics| {
+            metrics.num_redirects = num_redirects;
+            metrics.redirects.push(Redirect {
+                status: status.as_u16(),
+                url: url.to_string(),
+                location: next_url.to_string(),
+            });
+        });
+
+        trace_info(&format!("Following redirect to {}", next_url));
+        strip_credentials = strip_credentials || (!args.location_trusted && is_cross_origin(&url, &next_url));
+        challenged = false;
+        let keep_post = match status {
+            StatusCode::MOVED_PERMANENTLY => args.post301,
+            StatusCode::FOUND => args.post302,
+            StatusCode::SEE_OTHER => args.post303,
+            _ => false,
+        };
+        let (next_method, keep_body) = get_redirect_method(status, &method, keep_post);
+        method = next_method;
+        with_body = with_body && keep_body;
+        url = next_url;
+    }
+}
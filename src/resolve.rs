@@ -0,0 +1,256 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::args::Args;
+
+tokio::task_local! {
+    /// Host and port of the URL of the current request (see with_target).
+    static TARGET: Target;
+}
+
+#[derive(Clone)]
+struct Target {
+    host: String,
+    port: u16,
+    /// True, if the URL contains the port (the connection uses this port in any case).
+    explicit: bool,
+}
+
+/// Runs a request, so the resolver knows the port of the URL.
+///
+/// The resolver only receives the host name, but the entries of --resolve and
+/// --connect-to apply to a host and port.
+pub async fn with_target<F: Future>(url: &Url, request: F) -> F::Output {
+    let target = Target {
+        host: String::from(url.host_str().unwrap_or_default()),
+        port: url.port_or_known_default().unwrap_or(0),
+        explicit: url.port().is_some(),
+    };
+    TARGET.scope(target, request).await
+}
+
+/// Address family of the connections (-4, -6).
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Family {
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+/// Entry of --resolve: addresses of a host and port ('*' for all hosts).
+#[derive(Debug, PartialEq)]
+struct ResolveEntry {
+    host: String,
+    port: u16,
+    addrs: Vec<IpAddr>,
+}
+
+/// Entry of --connect-to: connections to host and port (None matches all) go to
+/// the host and port of the target (None keeps host or port of the request).
+#[derive(Debug, PartialEq)]
+struct ConnectToEntry {
+    host: Option<String>,
+    port: Option<u16>,
+    target_host: Option<String>,
+    target_port: Option<u16>,
+}
+
+/// Resolves host names applying --resolve, --connect-to, -4 and -6.
+///
+/// The Host header and the server name of TLS connections are not affected,
+/// since only the addresses of the connection are replaced.
+pub struct Resolver {
+    resolve: Vec<ResolveEntry>,
+    connect_to: Vec<ConnectToEntry>,
+    family: Family,
+}
+
+impl Resolver {
+    /// Returns None, if no option changes the resolution of host names.
+    pub fn new(args: &Args) -> Result<Option<Resolver>, String> {
+        let family = if args.ipv4 { Family::Ipv4 } else if args.ipv6 { Family::Ipv6 } else { Family::Any };
+        let resolve = args.resolve.iter()
+            .map(|value| parse_resolve(value).ok_or_else(|| format!("invalid --resolve value: {}", value)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let connect_to = args.connect_to.iter()
+            .map(|value| parse_connect_to(value).ok_or_else(|| format!("invalid --connect-to value: {}", value)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if resolve.is_empty() && connect_to.is_empty() && family == Family::Any {
+            return Ok(None);
+        }
+        Ok(Some(Resolver { resolve, connect_to, family }))
+    }
+
+    /// Returns host and port of the connection to the host of the request (after applying --connect-to).
+    fn get_connect_target(&self, target: &Target) -> Result<(String, u16), Error> {
+        let entry = self.connect_to.iter().find(|entry| {
+            entry.host.as_ref().is_none_or(|host| host.eq_ignore_ascii_case(&target.host))
+                && entry.port.is_none_or(|port| port == target.port)
+        });
+        let Some(entry) = entry else {
+            return Ok((target.host.clone(), target.port));
+        };
+
+        // the port of the URL replaces the port of the resolved addresses
+        let port = entry.target_port.unwrap_or(target.port);
+        if target.explicit && port != target.port {
+            return Err(Error::new(ErrorKind::Unsupported,
+                "--connect-to cannot change the port of URLs with an explicit port"));
+        }
+        Ok((entry.target_host.clone().unwrap_or_else(|| target.host.clone()), port))
+    }
+
+    /// Returns the addresses of --resolve for host and port.
+    fn get_resolved(&self, host: &str, port: Option<u16>) -> Option<Vec<IpAddr>> {
+        self.resolve.iter()
+            .find(|entry| (entry.host == "*" || entry.host.eq_ignore_ascii_case(host))
+                && port.is_none_or(|port| port == entry.port))
+            .map(|entry| entry.addrs.clone())
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        // other hosts (e.g. proxies) are resolved for all ports and --connect-to does not apply
+        let connect_target = match TARGET.try_with(|target| target.clone()) {
+            Ok(target) if target.host.eq_ignore_ascii_case(name.as_str()) => {
+                self.get_connect_target(&target).map(|(host, port)| (host, Some(port)))
+            },
+            _ => Ok((String::from(name.as_str()), None)),
+        };
+        let resolved = connect_target.as_ref().ok()
+            .and_then(|(host, port)| self.get_resolved(host, *port));
+        let family = self.family;
+
+        Box::pin(async move {
+            let (host, port) = connect_target?;
+            let port = port.unwrap_or(0);
+            let addrs: Vec<SocketAddr> = match resolved {
+                Some(addrs) => addrs.into_iter().map(|addr| SocketAddr::new(addr, port)).collect(),
+                None => tokio::net::lookup_host((host.as_str(), port)).await?.collect(),
+            };
+
+            let addrs: Vec<SocketAddr> = addrs.into_iter()
+                .filter(|addr| match family {
+                    Family::Any => true,
+                    Family::Ipv4 => addr.is_ipv4(),
+                    Family::Ipv6 => addr.is_ipv6(),
+                })
+                .collect();
+            if addrs.is_empty() {
+                let family = match family {
+                    Family::Ipv4 => "IPv4 ",
+                    Family::Ipv6 => "IPv6 ",
+                    Family::Any => "",
+                };
+                return Err(Error::new(ErrorKind::NotFound, format!("no {}address found for {}", family, host)).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Splits "host:rest" where host may be an IPv6 address in brackets.
+fn split_host(value: &str) -> Option<(&str, &str)> {
+    if let Some(value) = value.strip_prefix('[') {
+        let (host, rest) = value.split_once(']')?;
+        return Some((host, rest.strip_prefix(':')?));
+    }
+    value.split_once(':')
+}
+
+/// Parses "host:port:addr[,addr]..." (--resolve).
+fn parse_resolve(value: &str) -> Option<ResolveEntry> {
+    let (host, rest) = split_host(value)?;
+    let (port, addrs) = rest.split_once(':')?;
+    let addrs = addrs.split(',')
+        .map(|addr| addr.trim().trim_start_matches('[').trim_end_matches(']').parse().ok())
+        .collect::<Option<Vec<IpAddr>>>()?;
+    if host.is_empty() {
+        return None;
+    }
+    Some(ResolveEntry { host: host.to_ascii_lowercase(), port: port.parse().ok()?, addrs })
+}
+
+/// Parses "host1:port1:host2:port2" (--connect-to); empty parts match all
+/// hosts or ports respectively keep host or port of the request.
+fn parse_connect_to(value: &str) -> Option<ConnectToEntry> {
+    let (host, rest) = split_host(value)?;
+    let (port, rest) = rest.split_once(':')?;
+    let (target_host, target_port) = split_host(rest)?;
+
+    let parse_host = |host: &str| if host.is_empty() { None } else { Some(host.to_ascii_lowercase()) };
+    let parse_port = |port: &str| if port.is_empty() { Some(None) } else { port.parse().ok().map(Some) };
+    Some(ConnectToEntry {
+        host: parse_host(host),
+        port: parse_port(port)?,
+        target_host: parse_host(target_host),
+        target_port: parse_port(target_port)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolve() {
+        assert_eq!(Some(ResolveEntry {
+            host: String::from("example.com"),
+            port: 443,
+            addrs: vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()],
+        }), parse_resolve("Example.com:443:127.0.0.1,[::1]"));
+        assert_eq!(Some(String::from("*")), parse_resolve("*:80:10.0.0.1").map(|entry| entry.host));
+        assert_eq!(None, parse_resolve("example.com:443"));
+        assert_eq!(None, parse_resolve("example.com:https:127.0.0.1"));
+        assert_eq!(None, parse_resolve("example.com:443:localhost"));
+    }
+
+    #[test]
+    fn test_parse_connect_to() {
+        assert_eq!(Some(ConnectToEntry {
+            host: Some(String::from("example.com")),
+            port: Some(443),
+            target_host: Some(String::from("::1")),
+            target_port: Some(8443),
+        }), parse_connect_to("example.com:443:[::1]:8443"));
+        assert_eq!(Some(ConnectToEntry {
+            host: None,
+            port: None,
+            target_host: Some(String::from("mirror")),
+            target_port: None,
+        }), parse_connect_to("::mirror:"));
+        assert_eq!(None, parse_connect_to("example.com:443:mirror"));
+        assert_eq!(None, parse_connect_to("example.com:x:mirror:443"));
+    }
+
+    #[test]
+    fn test_connect_target() {
+        let resolver = Resolver {
+            resolve: vec![parse_resolve("mirror:8443:10.0.0.1").unwrap()],
+            connect_to: vec![parse_connect_to("example.com:443:mirror:8443").unwrap()],
+            family: Family::Any,
+        };
+        let target = |port, explicit| Target { host: String::from("example.com"), port, explicit };
+        let (host, port) = resolver.get_connect_target(&target(443, false)).unwrap();
+        assert_eq!(("mirror", 8443), (host.as_str(), port));
+        assert_eq!(Some(vec!["10.0.0.1".parse().unwrap()]), resolver.get_resolved(&host, Some(port)));
+        assert_eq!(None, resolver.get_resolved(&host, Some(443)));
+
+        let (host, port) = resolver.get_connect_target(&target(80, false)).unwrap();
+        assert_eq!(("example.com", 80), (host.as_str(), port));
+
+        let resolver = Resolver {
+            resolve: Vec::new(),
+            connect_to: vec![parse_connect_to(":8080:mirror:9090").unwrap()],
+            family: Family::Any,
+        };
+        assert!(resolver.get_connect_target(&target(8080, true)).is_err());
+        assert!(resolver.get_connect_target(&target(8081, true)).is_ok());
+    }
+}
@@ -0,0 +1,372 @@
+use futures_util::future::join_all;
+use log::{info, warn, error};
+use reqwest::header::HeaderValue;
+use reqwest::{header, Client, Response, StatusCode, Url, Version};
+use std::cell::RefCell;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::time::sleep;
+
+use crate::args::Args;
+use crate::progress::Progress;
+use crate::throttle::{next_chunk, send_request, RateLimiter, SpeedCheck};
+use crate::metrics::{set_error_type, update_metrics, ErrorType};
+use crate::redirect::remove_sensitive_headers;
+use crate::resolve::with_target;
+use crate::auth::add_auth;
+use crate::request::add_headers;
+use crate::trace::{trace_request, trace_response};
+use crate::tls::{get_verify_error, EXIT_CERT_STATUS, EXIT_PINNED_PUBKEY};
+use crate::{exit, verify_checksums, Checksums, EXIT_OPERATION_TIMEOUT};
+
+/// Number of times a failed segment is retried before the download is aborted.
+const MAX_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a segment, doubled with each further retry.
+const RETRY_DELAY_MS: u64 = 250;
+
+#[derive(Debug, PartialEq)]
+struct Segment {
+    start: u64,
+    end: u64,
+}
+
+/// Checks if the server allows to download the artifact of a response in segments.
+pub fn supports_segments(response: &Response) -> bool {
+    let accepts_bytes = response.headers().get_all(header::ACCEPT_RANGES).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"));
+
+    // ranges of encoded content refer to the encoded data
+    let is_encoded = response.headers().contains_key(header::CONTENT_ENCODING);
+
+    accepts_bytes
+        && !is_encoded
+        && response.status() == StatusCode::OK
+        && response.content_length().unwrap_or(0) > 0
+}
+
+/// Splits the content into (at most) count segments of about the same size.
+fn get_segments(content_length: u64, count: u64) -> Vec<Segment> {
+    let count = count.clamp(1, content_length.max(1));
+    let size = content_length / count;
+    let remainder = content_length % count;
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for i in 0..count {
+        let length = size + if i < remainder { 1 } else { 0 };
+        segments.push(Segment { start, end: start + length - 1 });
+        start += length;
+    }
+    segments
+}
+
+/// Returns the first byte position of a Content-Range header value (e.g. "bytes 0-99/100").
+fn get_range_start(content_range: &str) -> Option<u64> {
+    let range = content_range.trim().strip_prefix("bytes")?.trim_start();
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Returns the validator sent as If-Range with the range requests: the ETag of
+/// the initial response, if it is strong, otherwise its Last-Modified date.
+fn get_validator(response: &Response) -> Option<HeaderValue> {
+    let etag = response.headers().get(header::ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"));
+    etag.or_else(|| response.headers().get(header::LAST_MODIFIED)).cloned()
+}
+
+/// Error of a segment download, carries the exit code used once all retries failed.
+/// Fatal errors (e.g. a rejected certificate) are not retried.
+struct SegmentError {
+    exit_code: i32,
+    message: String,
+    fatal: bool,
+}
+
+impl SegmentError {
+    fn fatal(exit_code: i32, message: String) -> Self {
+        SegmentError { exit_code, message, fatal: true }
+    }
+}
+
+impl From<String> for SegmentError {
+    fn from(message: String) -> Self {
+        SegmentError { exit_code: 1, message, fatal: false }
+    }
+}
+
+impl From<&str> for SegmentError {
+    fn from(message: &str) -> Self {
+        SegmentError::from(String::from(message))
+    }
+}
+
+/// State shared by all segments of a download.
+struct Download<'a> {
+    client: &'a Client,
+    args: &'a Args,
+    url: &'a Url,
+    /// ETag or Last-Modified of the initial response (see get_validator).
+    validator: Option<HeaderValue>,
+    /// HTTP version of the initial response (reqwest uses HTTP/3 only if requested).
+    version: Version,
+    strip_credentials: bool,
+    filename: &'a PathBuf,
+    limiter: RefCell<RateLimiter>,
+    progress: RefCell<Progress>,
+}
+
+/// Sends a range request for the bytes from offset up to end (inclusive).
+///
+/// If-Range makes the server send the full content instead, if the resource
+/// changed since the initial response; the download is aborted in that case,
+/// since the segments would belong to different versions.
+async fn request_range(download: &Download<'_>, offset: u64, end: u64, speed_check: &Option<SpeedCheck>) -> Result<Response, SegmentError> {
+    let args = download.args;
+    let mut builder = add_headers(download.client.get(download.url.clone()), args)
+        .header(header::RANGE, format!("bytes={}-{}", offset, end));
+    if let Some(validator) = &download.validator {
+        builder = builder.header(header::IF_RANGE, validator);
+    }
+    if download.version == Version::HTTP_3 {
+        builder = builder.version(Version::HTTP_3);
+    }
+    let request = builder.build();
+    let mut request = request.map_err(|err| err.to_string())?;
+    if download.strip_credentials {
+        remove_sensitive_headers(request.headers_mut());
+    }
+    add_auth(&mut request, !download.strip_credentials);
+    trace_request(&request);
+    let response = send_request(with_target(download.url, download.client.execute(request)), speed_check).await
+        .map_err(|message| SegmentError { exit_code: EXIT_OPERATION_TIMEOUT, message, fatal: false })?
+        .map_err(|err| match get_verify_error(download.url) {
+            Some((exit_code, message)) => SegmentError::fatal(exit_code, message),
+            None => err.to_string().into(),
+        })?;
+    trace_response(&response);
+
+    let status = response.status();
+    if status == StatusCode::OK {
+        return Err(SegmentError::fatal(1, String::from("resource changed during download")));
+    }
+    if status != StatusCode::PARTIAL_CONTENT {
+        return Err(format!("bad http status: {}", status.as_u16()).into());
+    }
+
+    let start = response.headers().get(header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(get_range_start);
+    if start != Some(offset) {
+        return Err("server responded with an unexpected content range".into());
+    }
+
+    Ok(response)
+}
+
+/// Downloads the bytes from offset up to end (inclusive) and writes them to file.
+/// The offset is advanced for each byte written, so a retry can resume at that position.
+///
+/// If a response is given, its body is used instead of a range request; it has to
+/// start at offset (i.e. the full content of the initial response for offset 0).
+async fn fetch_range(download: &Download<'_>, offset: &mut u64, end: u64, file: &mut File, response: Option<Response>) -> Result<(), SegmentError> {
+    if file.seek(SeekFrom::Start(*offset)).await.is_err() {
+        return Err("failed to seek file".into());
+    }
+
+    let mut speed_check = SpeedCheck::new(download.args);
+    let response = match response {
+        Some(response) => response,
+        None => request_range(download, *offset, end, &speed_check).await?,
+    };
+
+    let mut stream = response.bytes_stream();
+    loop {
+        let item = next_chunk(&mut stream, &mut speed_check).await
+            .map_err(|message| SegmentError { exit_code: EXIT_OPERATION_TIMEOUT, message, fatal: false })?;
+        let item = match item {
+            Some(item) => item,
+            None => { break; }
+        };
+        if item.is_err() {
+            return Err("failed to read response data".into());
+        }
+
+        let data = item.unwrap();
+        let remaining = (end + 1 - *offset) as usize;
+        let data = &data[..data.len().min(remaining)];
+        if file.write_all(data).await.is_err() {
+            return Err("failed to write file".into());
+        }
+        *offset += data.len() as u64;
+        update_metrics(|metrics| {
+            metrics.size_download += data.len() as u64;
+            metrics.size_written += data.len() as u64;
+        });
+        download.progress.borrow_mut().update(data.len());
+
+        // the rate limit is shared by all segments
+        let delay = download.limiter.borrow_mut().delay(data.len());
+        if let Some(delay) = delay {
+            sleep(delay).await;
+        }
+
+        if *offset > end {
+            return Ok(());
+        }
+    }
+
+    Err("connection closed before segment was complete".into())
+}
+
+/// Downloads a segment and retries on failure; returns the exit code on error.
+async fn download_segment(download: &Download<'_>, segment: &Segment, response: Option<Response>) -> Result<(), i32> {
+    let file = OpenOptions::new().write(true).open(download.filename).await;
+    if file.is_err() {
+        set_error_type(ErrorType::File);
+        error!("failed to open file");
+        return Err(1);
+    }
+    let mut file = file.unwrap();
+
+    let mut offset = segment.start;
+    let mut retries = 0;
+    let mut response = response;
+    loop {
+        match fetch_range(download, &mut offset, segment.end, &mut file, response.take()).await {
+            Ok(()) => {
+                return Ok(());
+            },
+            Err(err) if !err.fatal && retries < MAX_RETRIES => {
+                retries += 1;
+                warn!("segment {}-{} failed ({}), retry {} of {}",
+                    segment.start, segment.end, err.message, retries, MAX_RETRIES);
+                sleep(Duration::from_millis(RETRY_DELAY_MS << (retries - 1))).await;
+            },
+            Err(err) => {
+                set_error_type(match err.exit_code {
+                    EXIT_OPERATION_TIMEOUT => ErrorType::Timeout,
+                    EXIT_PINNED_PUBKEY | EXIT_CERT_STATUS => ErrorType::Tls,
+                    _ => ErrorType::Transfer,
+                });
+                error!("segment {}-{} failed: {}", segment.start, segment.end, err.message);
+                return Err(err.exit_code);
+            }
+        }
+    }
+}
+
+/// Downloads an artifact using parallel range requests.
+///
+/// The output file is preallocated and each segment is written to its
+/// position. The body of the initial response is used for the first segment,
+/// so its data is not requested again. Checksums are verified after all
+/// segments are complete.
+pub async fn download_segments(client: &Client, args: &Args, strip_credentials: bool, response: Response, filename: &PathBuf) {
+    let url = response.url().clone();
+    let content_length = response.content_length().unwrap_or(0);
+    let file = File::create(filename).await;
+    if file.is_err() {
+        set_error_type(ErrorType::File);
+        error!("failed to create file");
+        exit(1);
+    }
+    if file.unwrap().set_len(content_length).await.is_err() {
+        set_error_type(ErrorType::File);
+        error!("failed to allocate file");
+        let _ = std::fs::remove_file(filename);
+        exit(1);
+    }
+
+    let segments = get_segments(content_length, args.segments as u64);
+    info!("downloading {} bytes in {} segments", content_length, segments.len());
+
+    let download = Download {
+        client,
+        args,
+        url: &url,
+        validator: get_validator(&response),
+        version: response.version(),
+        strip_credentials,
+        filename,
+        limiter: RefCell::new(RateLimiter::new(args.limit_rate)),
+        progress: RefCell::new(Progress::new(args, Some(content_length))),
+    };
+    let mut response = Some(response);
+    let results = join_all(segments.iter()
+        .map(|segment| download_segment(&download, segment, response.take()))).await;
+    download.progress.borrow_mut().finish();
+    if let Some(Err(exit_code)) = results.into_iter().find(|result| result.is_err()) {
+        let _ = std::fs::remove_file(filename);
+        exit(exit_code);
+    }
+
+    let mut checksums = Checksums::new();
+    {
+        let file = File::open(filename).await;
+        if file.is_err() {
+            set_error_type(ErrorType::File);
+            error!("failed to open file");
+            let _ = std::fs::remove_file(filename);
+            exit(1);
+        }
+        let mut file = file.unwrap();
+
+        let mut buffer: [u8; 10 * 1024]= [0; 10 * 1024];
+        loop {
+            let result = file.read(&mut buffer).await;
+            if result.is_err() {
+                set_error_type(ErrorType::File);
+                error!("failed to read file");
+                let _ = std::fs::remove_file(filename);
+                exit(1);
+            }
+
+            let i = result.unwrap();
+            if i == 0 {
+                break;
+            }
+            checksums.update(&buffer[0..i]);
+        }
+    }
+
+    verify_checksums(args, checksums, filename);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_segments() {
+        let segments = get_segments(10, 3);
+        assert_eq!(vec![
+            Segment { start: 0, end: 3 },
+            Segment { start: 4, end: 6 },
+            Segment { start: 7, end: 9 },
+        ], segments);
+
+        let segments = get_segments(2, 4);
+        assert_eq!(vec![
+            Segment { start: 0, end: 0 },
+            Segment { start: 1, end: 1 },
+        ], segments);
+
+        let segments = get_segments(100, 1);
+        assert_eq!(vec![Segment { start: 0, end: 99 }], segments);
+    }
+
+    #[test]
+    fn test_get_range_start() {
+        assert_eq!(Some(0), get_range_start("bytes 0-99/100"));
+        assert_eq!(Some(42), get_range_start("bytes 42-99/*"));
+        assert_eq!(None, get_range_start("bytes */100"));
+        assert_eq!(None, get_range_start("items 0-1/2"));
+    }
+}
@@ -0,0 +1,177 @@
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use reqwest::Response;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::{sleep, timeout, timeout_at, Instant};
+
+use crate::args::Args;
+
+/// Default value of --speed-time, if only --speed-limit is specified.
+const DEFAULT_SPEED_TIME: u64 = 30;
+
+/// Default value of --speed-limit, if only --speed-time is specified.
+const DEFAULT_SPEED_LIMIT: u64 = 1;
+
+/// Parses a transfer rate in bytes per second with an optional K, M or G suffix.
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, factor) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[..value.len() - 1], 1024),
+        Some('m') | Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    let number = number.parse::<u64>().map_err(|_| format!("invalid rate: {}", value))?;
+    number.checked_mul(factor).ok_or(format!("rate too large: {}", value))
+}
+
+/// Limits the transfer rate to a given number of bytes per second.
+pub struct RateLimiter {
+    rate: u64,
+    start: Instant,
+    count: u64,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        RateLimiter { rate: rate.unwrap_or(0), start: Instant::now(), count: 0 }
+    }
+
+    /// Accounts for count bytes and returns the time to wait to stay within the rate.
+    pub fn delay(&mut self, count: usize) -> Option<Duration> {
+        if self.rate == 0 {
+            return None;
+        }
+
+        self.count += count as u64;
+        let expected = Duration::from_secs_f64(self.count as f64 / self.rate as f64);
+        expected.checked_sub(self.start.elapsed())
+    }
+
+    /// Accounts for count bytes and waits, if the rate is exceeded.
+    pub async fn throttle(&mut self, count: usize) {
+        if let Some(delay) = self.delay(count) {
+            sleep(delay).await;
+        }
+    }
+}
+
+/// Aborts transfers which are slower than --speed-limit for --speed-time seconds.
+pub struct SpeedCheck {
+    limit: u64,
+    time: Duration,
+    window_start: Instant,
+    window_count: u64,
+}
+
+impl SpeedCheck {
+    /// Creates a speed check; returns None if no speed limit is specified.
+    pub fn new(args: &Args) -> Option<Self> {
+        let (limit, time) = match (args.speed_limit, args.speed_time) {
+            (None, None) => { return None; },
+            (Some(limit), None) => (limit, DEFAULT_SPEED_TIME),
+            (None, Some(time)) => (DEFAULT_SPEED_LIMIT, time),
+            (Some(limit), Some(time)) => (limit, time),
+        };
+
+        if limit == 0 || time == 0 {
+            return None;
+        }
+
+        Some(SpeedCheck {
+            limit,
+            time: Duration::from_secs(time),
+            window_start: Instant::now(),
+            window_count: 0,
+        })
+    }
+
+    fn error_message(&self) -> String {
+        format!("operation too slow: less than {} bytes/sec transferred the last {} seconds",
+            self.limit, self.time.as_secs())
+    }
+
+    fn check(&mut self) -> Result<(), String> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= self.time {
+            let speed = self.window_count as f64 / elapsed.as_secs_f64();
+            if speed < self.limit as f64 {
+                return Err(self.error_message());
+            }
+
+            self.window_start = Instant::now();
+            self.window_count = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the next chunk of data, but fails if the transfer is too slow.
+    pub async fn next<S>(&mut self, stream: &mut S) -> Result<Option<reqwest::Result<Bytes>>, String>
+    where S: Stream<Item = reqwest::Result<Bytes>> + Unpin
+    {
+        loop {
+            let deadline = self.window_start + self.time;
+            if let Ok(item) = timeout_at(deadline, stream.next()).await {
+                if let Some(Ok(ref data)) = item {
+                    self.window_count += data.len() as u64;
+                }
+                self.check()?;
+                return Ok(item);
+            }
+            self.check()?;
+        }
+    }
+}
+
+/// Sends a request, but fails if no response is received within --speed-time.
+pub async fn send_request<F>(request: F, speed_check: &Option<SpeedCheck>) -> Result<reqwest::Result<Response>, String>
+where F: Future<Output = reqwest::Result<Response>>
+{
+    match speed_check {
+        Some(speed_check) => timeout(speed_check.time, request).await
+            .map_err(|_| speed_check.error_message()),
+        None => Ok(request.await),
+    }
+}
+
+/// Returns the next chunk of data of a stream, applying the speed check if specified.
+pub async fn next_chunk<S>(stream: &mut S, speed_check: &mut Option<SpeedCheck>) -> Result<Option<reqwest::Result<Bytes>>, String>
+where S: Stream<Item = reqwest::Result<Bytes>> + Unpin
+{
+    match speed_check {
+        Some(speed_check) => speed_check.next(stream).await,
+        None => Ok(stream.next().await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(Ok(42), parse_rate("42"));
+        assert_eq!(Ok(2 * 1024), parse_rate("2k"));
+        assert_eq!(Ok(2 * 1024), parse_rate("2K"));
+        assert_eq!(Ok(3 * 1024 * 1024), parse_rate("3M"));
+        assert_eq!(Ok(1024 * 1024 * 1024), parse_rate("1G"));
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("K").is_err());
+        assert!(parse_rate("1T").is_err());
+        assert!(parse_rate("-1").is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(None);
+        assert_eq!(None, limiter.delay(1024 * 1024));
+
+        let mut limiter = RateLimiter::new(Some(1024));
+        let delay = limiter.delay(2048).unwrap();
+        assert!(delay > Duration::from_millis(1500));
+        assert!(delay <= Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,702 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use clap::ValueEnum;
+use reqwest::tls::Version;
+use reqwest::Url;
+use rustls::crypto::{ring, CryptoProvider, SupportedKxGroup};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer, ServerName, SubjectPublicKeyInfoDer, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, KeyLogFile, RootCertStore, SignatureScheme, SupportedCipherSuite, SupportedProtocolVersion};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use x509_parser::prelude::{CertificateRevocationList, FromDer, SubjectPublicKeyInfo, X509Certificate};
+
+use crate::args::Args;
+use crate::certinfo::{is_session_recorded, record_certificates, record_cipher_suites};
+use crate::identity::{load_client_cert, CertOptions};
+use crate::ocsp::verify_stapled_response;
+use crate::writeout::uses_tls_session;
+
+/// Exit code used when the public key of the server does not match --pinnedpubkey (same as curl).
+pub const EXIT_PINNED_PUBKEY: i32 = 90;
+
+/// Exit code used when the stapled OCSP response is missing or invalid (--cert-status, same as curl).
+pub const EXIT_CERT_STATUS: i32 = 91;
+
+/// Error message reported when the public key of the server does not match --pinnedpubkey.
+const PIN_MISMATCH_ERROR: &str = "SSL: public key does not match pinned public key";
+
+/// Set by the certificate verifier, since the error returned by reqwest does not
+/// allow to distinguish a pin mismatch or an invalid certificate status from other
+/// TLS errors. Errors are stored by server name and removed once reported, so they
+/// do not apply to later requests (e.g. after a redirect).
+static VERIFY_ERROR: Mutex<Vec<(String, i32, String)>> = Mutex::new(Vec::new());
+
+/// Returns exit code and message, if a connection to the host was rejected
+/// because of --pinnedpubkey or --cert-status; the error is removed.
+pub fn get_verify_error(url: &Url) -> Option<(i32, String)> {
+    let host = get_server_name(url.host_str().unwrap_or_default());
+    let mut errors = VERIFY_ERROR.lock().unwrap();
+    let index = errors.iter().position(|(name, _, _)| *name == host)?;
+    let (_, exit_code, message) = errors.remove(index);
+    Some((exit_code, message))
+}
+
+fn set_verify_error(server_name: &ServerName<'_>, exit_code: i32, message: String) -> rustls::Error {
+    let host = get_server_name(&server_name.to_str());
+    let mut errors = VERIFY_ERROR.lock().unwrap();
+    errors.retain(|(name, _, _)| *name != host);
+    errors.push((host, exit_code, message.clone()));
+    rustls::Error::General(message)
+}
+
+/// Normalizes a host name, IPv6 addresses of URLs are enclosed in brackets.
+fn get_server_name(host: &str) -> String {
+    host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase()
+}
+
+/// OpenSSL names of the TLS 1.2 cipher suites supported by rustls (--ciphers).
+const OPENSSL_CIPHER_NAMES: [(&str, &str); 6] = [
+    ("ECDHE-ECDSA-AES256-GCM-SHA384", "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"),
+    ("ECDHE-ECDSA-AES128-GCM-SHA256", "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"),
+    ("ECDHE-ECDSA-CHACHA20-POLY1305", "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256"),
+    ("ECDHE-RSA-AES256-GCM-SHA384", "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"),
+    ("ECDHE-RSA-AES128-GCM-SHA256", "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"),
+    ("ECDHE-RSA-CHACHA20-POLY1305", "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256"),
+];
+
+/// Alternative names of the key exchange groups (--curves).
+const CURVE_ALIASES: [(&str, &str); 4] = [
+    ("P-256", "secp256r1"),
+    ("prime256v1", "secp256r1"),
+    ("P-384", "secp384r1"),
+    ("x25519", "X25519"),
+];
+
+/// TLS implementation used for connections to servers (--tls-backend).
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TlsBackend {
+    Rustls,
+    /// Platform TLS library (OpenSSL on Linux); only available with the native-tls feature.
+    Native,
+}
+
+impl TlsBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsBackend::Rustls => "rustls",
+            TlsBackend::Native => "native",
+        }
+    }
+}
+
+/// Returns the TLS backend of --tls-backend. Without --tls-backend, rustls is used
+/// if an option requires it and the native backend otherwise (if available).
+/// Fails, if the TLS options cannot be used together.
+pub fn get_tls_backend(args: &Args) -> Result<TlsBackend, String> {
+    let requires_rustls = [
+        (args.tlsv1_3, "--tlsv1.3"),
+        (args.http2, "--http2"),
+        (args.http3, "--http3"),
+        (args.http3_only, "--http3-only"),
+        (args.ciphers.is_some(), "--ciphers"),
+        (args.tls13_ciphers.is_some(), "--tls13-ciphers"),
+        (args.curves.is_some(), "--curves"),
+        (args.pinnedpubkey.is_some(), "--pinnedpubkey"),
+        (args.cert_status, "--cert-status"),
+        (args.show_certs, "--show-certs"),
+        (uses_tls_session(), "--write-out %{certs}"),
+        // CRLs are ignored by the native backend of reqwest
+        (!args.crlfile.is_empty(), "--crlfile"),
+    ].into_iter().find(|(is_set, _)| *is_set).map(|(_, option)| option);
+    let requires_native = matches!(args.tls_max.as_deref(), Some("1.0") | Some("1.1"));
+
+    let min_version = [(args.tlsv1_3, "1.3"), (args.tlsv1_2, "1.2"), (args.tlsv1_1, "1.1")].into_iter()
+        .find(|(is_set, _)| *is_set)
+        .map(|(_, version)| version);
+    if let (Some(min_version), Some(max_version)) = (min_version, args.tls_max.as_deref()) {
+        if max_version < min_version {
+            return Err(format!("--tls-max {} is lower than the minimum TLS version {}", max_version, min_version));
+        }
+    }
+
+    let backend = match args.tls_backend {
+        Some(backend) => backend,
+        None if requires_rustls.is_some() || !cfg!(feature = "native-tls") => TlsBackend::Rustls,
+        None => TlsBackend::Native,
+    };
+    match backend {
+        TlsBackend::Rustls if requires_native => {
+            Err(String::from("--tls-max below 1.2 requires the native TLS backend (rustls supports TLS 1.2 and 1.3 only)"))
+        },
+        TlsBackend::Native if !cfg!(feature = "native-tls") => {
+            Err(String::from("the native TLS backend is not available in this build"))
+        },
+        TlsBackend::Native if requires_rustls.is_some() => {
+            Err(format!("{} requires the rustls TLS backend", requires_rustls.unwrap_or_default()))
+        },
+        backend => Ok(backend),
+    }
+}
+
+/// Returns the maximum TLS version of --tls-max.
+///
+/// TLS 1.3 cannot be set as maximum for the native backend, but no newer
+/// version exists, so no maximum is set in this case.
+pub fn get_max_tls_version(args: &Args, backend: TlsBackend) -> Option<Version> {
+    match args.tls_max.as_deref() {
+        Some("1.0") => Some(Version::TLS_1_0),
+        Some("1.1") => Some(Version::TLS_1_1),
+        Some("1.2") => Some(Version::TLS_1_2),
+        Some("1.3") if backend == TlsBackend::Rustls => Some(Version::TLS_1_3),
+        _ => None,
+    }
+}
+
+/// Installs the crypto provider of rustls used for all connections to servers,
+/// restricted to the cipher suites and key exchange groups of --ciphers,
+/// --tls13-ciphers and --curves. The provider is also used by reqwest.
+pub fn install_crypto_provider(args: &Args) -> Result<(), String> {
+    let mut provider = ring::default_provider();
+    let (tls13, tls12): (Vec<_>, Vec<_>) = provider.cipher_suites.iter()
+        .partition(|suite| suite.version() == &rustls::version::TLS13);
+
+    let mut cipher_suites = Vec::new();
+    match &args.tls13_ciphers {
+        Some(ciphers) => cipher_suites.extend(select_cipher_suites(ciphers, &tls13)?),
+        None => cipher_suites.extend(tls13),
+    }
+    match &args.ciphers {
+        Some(ciphers) => cipher_suites.extend(select_cipher_suites(ciphers, &tls12)?),
+        None => cipher_suites.extend(tls12),
+    }
+    provider.cipher_suites = cipher_suites;
+
+    if let Some(curves) = &args.curves {
+        provider.kx_groups = select_kx_groups(curves, &provider.kx_groups)?;
+    }
+
+    // fails only if called twice, which does not happen
+    let _ = provider.install_default();
+    Ok(())
+}
+
+/// Returns the crypto provider used for connections to servers.
+fn get_crypto_provider() -> Arc<CryptoProvider> {
+    CryptoProvider::get_default().cloned()
+        .unwrap_or_else(|| Arc::new(ring::default_provider()))
+}
+
+/// Returns the cipher suites of a colon separated list of IANA (or OpenSSL) names.
+fn select_cipher_suites(list: &str, available: &[SupportedCipherSuite]) -> Result<Vec<SupportedCipherSuite>, String> {
+    let mut result = Vec::new();
+    for name in list.split([':', ',', ' ']).filter(|name| !name.is_empty()) {
+        let iana_name = OPENSSL_CIPHER_NAMES.iter()
+            .find(|(openssl_name, _)| openssl_name.eq_ignore_ascii_case(name))
+            .map(|(_, iana_name)| *iana_name)
+            .unwrap_or(name);
+        let suite = available.iter()
+            .find(|suite| get_cipher_suite_name(suite).eq_ignore_ascii_case(iana_name))
+            .ok_or_else(|| format!("unsupported cipher suite: {}", name))?;
+        result.push(*suite);
+    }
+    Ok(result)
+}
+
+/// Returns the IANA name of a cipher suite, e.g. TLS_AES_128_GCM_SHA256.
+pub fn get_cipher_suite_name(suite: &SupportedCipherSuite) -> String {
+    let name = format!("{:?}", suite.suite());
+    match name.strip_prefix("TLS13_") {
+        Some(name) => format!("TLS_{}", name),
+        None => name,
+    }
+}
+
+/// Returns the key exchange groups of a colon separated list of names.
+fn select_kx_groups(list: &str, available: &[&'static dyn SupportedKxGroup]) -> Result<Vec<&'static dyn SupportedKxGroup>, String> {
+    let mut result = Vec::new();
+    for name in list.split([':', ',', ' ']).filter(|name| !name.is_empty()) {
+        let group_name = CURVE_ALIASES.iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+            .map(|(_, group_name)| *group_name)
+            .unwrap_or(name);
+        let group = available.iter()
+            .find(|group| format!("{:?}", group.name()).eq_ignore_ascii_case(group_name))
+            .ok_or_else(|| format!("unsupported curve: {}", name))?;
+        result.push(*group);
+    }
+    Ok(result)
+}
+
+/// Returns the TLS versions of rustls allowed by --tlsv1.3 and --tls-max.
+fn get_rustls_versions(args: &Args) -> Vec<&'static SupportedProtocolVersion> {
+    let mut versions = Vec::new();
+    if !args.tlsv1_3 {
+        versions.push(&rustls::version::TLS12);
+    }
+    if args.tls_max.as_deref().is_none_or(|max| max == "1.3") {
+        versions.push(&rustls::version::TLS13);
+    }
+    versions
+}
+
+/// Trust settings and client certificate of TLS connections.
+struct TlsOptions<'a> {
+    provider: Arc<CryptoProvider>,
+    /// True, if the root certificates of webpki-roots are trusted.
+    builtin_roots: bool,
+    ca_certs: Vec<CertificateDer<'static>>,
+    crls: Vec<CertificateRevocationListDer<'static>>,
+    insecure: bool,
+    pins: Vec<Vec<u8>>,
+    /// True, if the server has to staple a valid OCSP response (--cert-status).
+    cert_status: bool,
+    /// True, if the certificates of the server are recorded (--show-certs).
+    record_certs: bool,
+    client_cert: Option<CertOptions<'a>>,
+    versions: Vec<&'static SupportedProtocolVersion>,
+}
+
+/// Returns true, if the connections to servers use the rustls configuration of
+/// create_verifier_config instead of the TLS settings of reqwest.
+/// --http2, --http3 and --http3-only use it to select the ALPN protocols.
+pub fn requires_verifier_config(args: &Args, backend: TlsBackend) -> bool {
+    // reqwest does not support SSLKEYLOGFILE
+    let key_log = std::env::var_os("SSLKEYLOGFILE").is_some() && backend == TlsBackend::Rustls;
+    args.pinnedpubkey.is_some() || args.cert_status || is_session_recorded(args)
+        || args.http2 || args.http3 || args.http3_only || key_log
+}
+
+/// Creates the rustls configuration used for public key pinning (--pinnedpubkey),
+/// OCSP stapling (--cert-status), to record the TLS session (--show-certs) and
+/// to log the TLS keys (SSLKEYLOGFILE).
+///
+/// The pins and the stapled OCSP response are checked during the handshake of
+/// every connection, so no request is sent to a server with a different key or a
+/// revoked certificate. Since the configuration replaces the TLS settings of
+/// reqwest, --cacert, --crlfile, --insecure, the TLS versions, the client
+/// certificate and the HTTP version (ALPN) are applied here as well.
+pub fn create_verifier_config(args: &Args) -> Result<ClientConfig, String> {
+    // the cipher suites of this configuration record the TLS session (--show-certs)
+    let mut provider = get_crypto_provider();
+    if is_session_recorded(args) {
+        provider = Arc::new(CryptoProvider {
+            cipher_suites: record_cipher_suites(provider.cipher_suites.clone()),
+            ..(*provider).clone()
+        });
+    }
+    let mut config = create_config(TlsOptions {
+        provider,
+        builtin_roots: !args.no_system_ca && !args.ca_native,
+        ca_certs: load_ca_certificates(args, TlsBackend::Rustls)?,
+        crls: load_crl_files(&args.crlfile)?,
+        insecure: args.insecure,
+        pins: match &args.pinnedpubkey {
+            Some(pinnedpubkey) => parse_pins(pinnedpubkey)?,
+            None => Vec::new(),
+        },
+        cert_status: args.cert_status,
+        record_certs: is_session_recorded(args),
+        client_cert: CertOptions::server(args),
+        versions: get_rustls_versions(args),
+    })?;
+    // the configuration is used for QUIC as well, where only h3 is selected
+    config.alpn_protocols = if args.http1_1 {
+        vec![b"http/1.1".to_vec()]
+    }
+    else if args.http2_prior_knowledge {
+        vec![b"h2".to_vec()]
+    }
+    else if args.http3_only {
+        vec![b"h3".to_vec()]
+    }
+    else if args.http3 {
+        vec![b"h3".to_vec(), b"h2".to_vec(), b"http/1.1".to_vec()]
+    }
+    else {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    };
+    Ok(config)
+}
+
+/// Creates the rustls configuration of connections to HTTPS proxies, which uses
+/// --proxy-cacert, --proxy-crlfile, --proxy-insecure and --proxy-cert instead of
+/// the settings used for servers. Cipher suites, curves and TLS versions of the
+/// servers do not apply to the proxy either.
+pub fn create_proxy_config(args: &Args) -> Result<ClientConfig, String> {
+    create_config(TlsOptions {
+        provider: Arc::new(ring::default_provider()),
+        builtin_roots: true,
+        ca_certs: match &args.proxy_cacert {
+            Some(cacert) => load_certs(cacert)?,
+            None => Vec::new(),
+        },
+        crls: load_crl_files(args.proxy_crlfile.as_slice())?,
+        insecure: args.proxy_insecure,
+        pins: Vec::new(),
+        cert_status: false,
+        record_certs: false,
+        client_cert: CertOptions::proxy(args),
+        versions: rustls::DEFAULT_VERSIONS.to_vec(),
+    })
+}
+
+fn create_config(options: TlsOptions) -> Result<ClientConfig, String> {
+    let provider = options.provider;
+
+    let mut roots = RootCertStore::empty();
+    if options.builtin_roots {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    for cert in options.ca_certs.iter().cloned() {
+        roots.add(cert).map_err(|_| String::from("failed to load CA certificate"))?;
+    }
+
+    let mut verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone());
+    if !options.crls.is_empty() {
+        verifier = verifier.with_crls(options.crls);
+    }
+    let verifier = verifier.build()
+        .map_err(|err| format!("failed to create certificate verifier: {}", err))?;
+
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&options.versions)
+        .map_err(|err| format!("failed to create TLS configuration: {}", err))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(ServerVerifier {
+            inner: verifier,
+            provider,
+            pins: options.pins,
+            insecure: options.insecure,
+            cert_status: options.cert_status,
+            record_certs: options.record_certs,
+            ca_certs: options.ca_certs,
+        }));
+
+    let mut config = match options.client_cert {
+        Some(cert_options) => {
+            let (chain, key) = load_client_cert(&cert_options)?;
+            builder.with_client_auth_cert(chain, PrivateKeyDer::Pkcs8(key))
+                .map_err(|err| format!("failed to load client certificate: {}", err))?
+        },
+        None => builder.with_no_client_auth(),
+    };
+    // writes the keys to the file of SSLKEYLOGFILE (if set)
+    config.key_log = Arc::new(KeyLogFile::new());
+    Ok(config)
+}
+
+/// Parses the value of --pinnedpubkey: either "sha256//<base64>" hashes separated
+/// by ';' or the name of a file containing a PEM or DER encoded public key.
+/// Returns the SHA-256 hashes of the pinned public keys.
+fn parse_pins(value: &str) -> Result<Vec<Vec<u8>>, String> {
+    if value.starts_with("sha256//") {
+        let mut pins = Vec::new();
+        for pin in value.split(';') {
+            let hash = pin.trim().strip_prefix("sha256//")
+                .and_then(|hash| STANDARD.decode(hash).ok())
+                .filter(|hash| hash.len() == 32)
+                .ok_or_else(|| format!("invalid pinned public key hash: {}", pin))?;
+            pins.push(hash);
+        }
+        return Ok(pins);
+    }
+
+    let data = std::fs::read(value)
+        .map_err(|_| format!("failed to read pinned public key file: {}", value))?;
+    let der = if data.starts_with(b"-----BEGIN") {
+        SubjectPublicKeyInfoDer::from_pem_slice(&data).ok().map(|key| key.to_vec())
+    }
+    else {
+        Some(data)
+    };
+    let der = der.filter(|der| matches!(SubjectPublicKeyInfo::from_der(der), Ok((rest, _)) if rest.is_empty()))
+        .ok_or_else(|| format!("failed to load pinned public key: {}", value))?;
+    Ok(vec![Sha256::digest(der).to_vec()])
+}
+
+/// Returns the SHA-256 hash of the public key (SubjectPublicKeyInfo) of a certificate.
+fn get_public_key_hash(cert: &[u8]) -> Option<Vec<u8>> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    Some(Sha256::digest(cert.public_key().raw).to_vec())
+}
+
+/// Returns the CA certificates of --cacert and --capath and, if --ca-native is
+/// used with rustls, the certificates of the system store. The certificates are
+/// trusted in addition to the built-in root certificates (unless --no-system-ca).
+pub fn load_ca_certificates(args: &Args, backend: TlsBackend) -> Result<Vec<CertificateDer<'static>>, String> {
+    let mut certs = Vec::new();
+    for cacert in &args.cacert {
+        certs.extend(load_certs(cacert)?);
+    }
+    if let Some(capath) = &args.capath {
+        for dir in capath.split(':').filter(|dir| !dir.is_empty()) {
+            certs.extend(load_cert_dir(Path::new(dir))?);
+        }
+    }
+    // the native backend uses the system store anyway
+    if args.ca_native && backend == TlsBackend::Rustls {
+        certs.extend(load_native_certs()?);
+    }
+    Ok(certs)
+}
+
+/// Returns the CRLs of --crlfile.
+pub fn load_crl_files(files: &[String]) -> Result<Vec<CertificateRevocationListDer<'static>>, String> {
+    let mut crls = Vec::new();
+    for file in files {
+        crls.extend(load_crls(file)?);
+    }
+    Ok(crls)
+}
+
+/// Returns true, if the data is PEM encoded (DER otherwise).
+fn is_pem(data: &[u8]) -> bool {
+    data.windows(11).any(|window| window == b"-----BEGIN ")
+}
+
+/// Loads a PEM bundle or a DER encoded certificate.
+fn load_certs(filename: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let data = std::fs::read(filename)
+        .map_err(|_| format!("failed to read CA certificate file: {}", filename))?;
+    if !is_pem(&data) {
+        return match X509Certificate::from_der(&data) {
+            Ok(_) => Ok(vec![CertificateDer::from(data)]),
+            Err(_) => Err(format!("failed to load DER certificate: {}", filename)),
+        };
+    }
+    CertificateDer::pem_slice_iter(&data).collect::<Result<Vec<_>, _>>().ok()
+        .filter(|certs| !certs.is_empty())
+        .ok_or_else(|| format!("failed to load PEM certificate bundle: {}", filename))
+}
+
+/// Loads the certificates of a directory, e.g. a hashed directory of OpenSSL
+/// (c_rehash). Files which do not contain certificates (e.g. CRLs) are skipped.
+fn load_cert_dir(dir: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|_| format!("failed to read CA certificate directory: {}", dir.display()))?;
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+
+    let mut certs: Vec<CertificateDer<'static>> = Vec::new();
+    for path in paths.iter().filter(|path| path.is_file()) {
+        let loaded = path.to_str().and_then(|filename| load_certs(filename).ok()).unwrap_or_default();
+        for cert in loaded {
+            // hashed directories contain links to the certificate files
+            if !certs.contains(&cert) {
+                certs.push(cert);
+            }
+        }
+    }
+    Ok(certs)
+}
+
+/// Loads the certificates of the system store (the locations used by OpenSSL).
+fn load_native_certs() -> Result<Vec<CertificateDer<'static>>, String> {
+    let probe = openssl_probe::probe();
+    let certs = match (&probe.cert_file, probe.cert_dir.first()) {
+        (Some(file), _) => file.to_str().map(load_certs).unwrap_or_else(|| Ok(Vec::new()))?,
+        (None, Some(dir)) => load_cert_dir(dir)?,
+        (None, None) => Vec::new(),
+    };
+
+    // system stores may contain certificates which are not supported by rustls
+    let certs: Vec<_> = certs.into_iter()
+        .filter(|cert| RootCertStore::empty().add(cert.clone()).is_ok())
+        .collect();
+    if certs.is_empty() {
+        return Err(String::from("failed to find the certificates of the system store"));
+    }
+    Ok(certs)
+}
+
+/// Loads PEM (possibly multiple) or DER encoded CRLs.
+fn load_crls(filename: &str) -> Result<Vec<CertificateRevocationListDer<'static>>, String> {
+    let data = std::fs::read(filename)
+        .map_err(|_| format!("failed to read CRL file: {}", filename))?;
+    if !is_pem(&data) {
+        return match CertificateRevocationList::from_der(&data) {
+            Ok(_) => Ok(vec![CertificateRevocationListDer::from(data)]),
+            Err(_) => Err(format!("failed to load CRL: {}", filename)),
+        };
+    }
+    CertificateRevocationListDer::pem_slice_iter(&data).collect::<Result<Vec<_>, _>>().ok()
+        .filter(|crls| !crls.is_empty())
+        .ok_or_else(|| format!("failed to load CRL(s): {}", filename))
+}
+
+/// Verifies the server certificate (unless insecure), the stapled OCSP response
+/// (if required) and checks the public key against the pinned public keys (if any).
+#[derive(Debug)]
+struct ServerVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    provider: Arc<CryptoProvider>,
+    pins: Vec<Vec<u8>>,
+    insecure: bool,
+    cert_status: bool,
+    record_certs: bool,
+    /// Certificates of --cacert and --capath (issuers of OCSP responses).
+    ca_certs: Vec<CertificateDer<'static>>,
+}
+
+impl ServerCertVerifier for ServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        // recorded before the verification, so failed connections can be analyzed
+        if self.record_certs {
+            record_certificates(end_entity, intermediates);
+        }
+
+        if !self.insecure {
+            self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+
+        // the certificate status is checked even if insecure (like curl)
+        if self.cert_status {
+            verify_stapled_response(end_entity, intermediates, &self.ca_certs, ocsp_response)
+                .map_err(|message| set_verify_error(server_name, EXIT_CERT_STATUS, format!("SSL: {}", message)))?;
+        }
+
+        if self.pins.is_empty() {
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        let matches = get_public_key_hash(end_entity)
+            .map(|hash| self.pins.contains(&hash))
+            .unwrap_or(false);
+        if !matches {
+            return Err(set_verify_error(server_name, EXIT_PINNED_PUBKEY, String::from(PIN_MISMATCH_ERROR)));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_verify_error() {
+        let url = Url::parse("https://[::1]:9000/").unwrap();
+        let server_name = ServerName::try_from("::1").unwrap();
+        let _ = set_verify_error(&server_name, EXIT_PINNED_PUBKEY, String::from(PIN_MISMATCH_ERROR));
+        assert_eq!(None, get_verify_error(&Url::parse("https://localhost/").unwrap()));
+        assert_eq!(Some((EXIT_PINNED_PUBKEY, String::from(PIN_MISMATCH_ERROR))), get_verify_error(&url));
+
+        // reported once, so later requests are not affected
+        assert_eq!(None, get_verify_error(&url));
+
+        // a later error replaces the error of the same host
+        let url = Url::parse("https://OCSP.example.com/").unwrap();
+        let server_name = ServerName::try_from("ocsp.example.com").unwrap();
+        let _ = set_verify_error(&server_name, EXIT_PINNED_PUBKEY, String::from(PIN_MISMATCH_ERROR));
+        let _ = set_verify_error(&server_name, EXIT_CERT_STATUS, String::from("SSL: server certificate is revoked (OCSP)"));
+        assert_eq!(Some((EXIT_CERT_STATUS, String::from("SSL: server certificate is revoked (OCSP)"))), get_verify_error(&url));
+        assert_eq!(None, get_verify_error(&url));
+    }
+
+    #[test]
+    fn test_parse_pins() {
+        let hash = [7u8; 32];
+        let value = format!("sha256//{};sha256//{}", STANDARD.encode(hash), STANDARD.encode([8u8; 32]));
+        assert_eq!(Ok(vec![hash.to_vec(), vec![8u8; 32]]), parse_pins(&value));
+        assert!(parse_pins("sha256//invalid").is_err());
+        assert!(parse_pins(&format!("sha256//{};md5//abc", STANDARD.encode(hash))).is_err());
+        assert!(parse_pins("no-such-file.pem").is_err());
+    }
+
+    #[test]
+    fn test_get_tls_backend() {
+        let backend = |options: &[&str]| {
+            get_tls_backend(&Args::parse_from([&["fetch"], options, &["https://localhost/"]].concat()))
+        };
+        let default_backend = if cfg!(feature = "native-tls") { TlsBackend::Native } else { TlsBackend::Rustls };
+        assert_eq!(Ok(default_backend), backend(&[]));
+        assert_eq!(Ok(TlsBackend::Rustls), backend(&["--tls-backend", "rustls"]));
+        assert_eq!(Ok(TlsBackend::Rustls), backend(&["--curves", "X25519"]));
+        assert!(backend(&["--tls-backend", "native", "--ciphers", "ECDHE-RSA-AES128-GCM-SHA256"]).is_err());
+        assert!(backend(&["--tls-backend", "rustls", "--tls-max", "1.1"]).is_err());
+        assert!(backend(&["--tlsv1.2", "--tls-max", "1.1"]).is_err());
+        assert_eq!(Ok(TlsBackend::Rustls), backend(&["--crlfile", "test/certs/proxy-revoked.crl"]));
+    }
+
+    #[test]
+    fn test_load_certs_and_crls() {
+        let pem = load_certs("test-utils/proxy-server/src/proxy-ca.pem").unwrap();
+        assert_eq!(pem, load_certs("test/certs/proxy-ca.crt").unwrap());
+        assert!(load_certs("src/main.rs").is_err());
+
+        assert_eq!(1, load_crls("test/certs/proxy-revoked.crl").unwrap().len());
+        assert_eq!(1, load_crls("test/certs/proxy-empty-crl.pem").unwrap().len());
+        assert!(load_crls("test/certs/proxy-ca.crt").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy("test/certs/proxy-ca.crt", dir.path().join("ca.crt")).unwrap();
+        std::fs::copy("test/certs/proxy-ca.crt", dir.path().join("0a1b2c3d.0")).unwrap();
+        std::fs::copy("test/certs/proxy-revoked.crl", dir.path().join("ca.crl")).unwrap();
+        assert_eq!(pem, load_cert_dir(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_select_cipher_suites() {
+        let available = ring::default_provider().cipher_suites;
+        let suites = select_cipher_suites("ECDHE-RSA-AES128-GCM-SHA256:TLS_AES_256_GCM_SHA384", &available).unwrap();
+        let names: Vec<_> = suites.iter().map(get_cipher_suite_name).collect();
+        assert_eq!(vec!["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256", "TLS_AES_256_GCM_SHA384"], names);
+        assert!(select_cipher_suites("RC4-MD5", &available).is_err());
+
+        let available = ring::default_provider().kx_groups;
+        let groups = select_kx_groups("P-256:x25519", &available).unwrap();
+        let names: Vec<_> = groups.iter().map(|group| format!("{:?}", group.name())).collect();
+        assert_eq!(vec!["secp256r1", "X25519"], names);
+        assert!(select_kx_groups("brainpoolP256r1", &available).is_err());
+    }
+
+    #[test]
+    fn test_parse_pins_from_file() {
+        let der = CertificateDer::from_pem_slice(include_bytes!("../test-utils/test-server/src/cert.pem")).unwrap();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        let public_key = cert.public_key().raw;
+        let expected = get_public_key_hash(&der).unwrap();
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let pem = pkcs8::der::pem::encode_string("PUBLIC KEY", pkcs8::der::pem::LineEnding::LF, public_key).unwrap();
+        std::fs::write(file.path(), pem).unwrap();
+        assert_eq!(Ok(vec![expected.clone()]), parse_pins(file.path().to_str().unwrap()));
+
+        std::fs::write(file.path(), public_key).unwrap();
+        assert_eq!(Ok(vec![expected]), parse_pins(file.path().to_str().unwrap()));
+    }
+}
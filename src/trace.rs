@@ -0,0 +1,444 @@
+use reqwest::tls::TlsInfo;
+use reqwest::{Request, Response, Url};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{stderr, stdout, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::args::Args;
+use crate::bridge::BRIDGE_TOKEN_HEADER;
+use crate::certinfo::{format_name, format_cert_time};
+use crate::headers::{format_headers, get_reason};
+use crate::metrics::format_version;
+use crate::report::SENSITIVE_HEADERS;
+
+/// Log target of reqwest's connection logging (see ClientBuilder::connection_verbose).
+///
+/// The wire data of --trace is taken from these log messages. Their format
+/// ("{:08x} read: {:?}" in reqwest's connect.rs) is not part of the API of
+/// reqwest and may change with any release, so reqwest is pinned to an exact
+/// version in Cargo.toml; check test_parse_wire_message when updating it.
+pub const WIRE_TARGET: &str = "reqwest::connect::verbose";
+
+/// Number of bytes per line of the hex dump.
+const HEX_WIDTH: usize = 16;
+
+/// Maximum number of characters per line of the ascii dump.
+const ASCII_WIDTH: usize = 64;
+
+#[derive(PartialEq)]
+enum Mode {
+    Verbose,
+    Hex,
+    Ascii,
+}
+
+enum Output {
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+impl Output {
+    fn write(&mut self, text: &str) {
+        let _ = match self {
+            Output::Stdout => stdout().write_all(text.as_bytes()),
+            Output::Stderr => stderr().write_all(text.as_bytes()),
+            Output::File(file) => file.write_all(text.as_bytes()),
+        };
+    }
+}
+
+struct State {
+    output: Output,
+    connections: Vec<u32>,
+    request_pending: bool,
+    new_connection: bool,
+}
+
+struct Trace {
+    mode: Mode,
+    time: bool,
+    state: Mutex<State>,
+}
+
+static TRACE: OnceLock<Trace> = OnceLock::new();
+
+/// Enables verbose output (-v) or a trace dump (--trace, --trace-ascii).
+/// A trace dump replaces the verbose output.
+pub fn init_trace(args: &Args) -> Result<(), String> {
+    let (mode, target) = if let Some(target) = &args.trace {
+        (Mode::Hex, target.as_str())
+    }
+    else if let Some(target) = &args.trace_ascii {
+        (Mode::Ascii, target.as_str())
+    }
+    else if args.verbose {
+        (Mode::Verbose, "%")
+    }
+    else {
+        return Ok(());
+    };
+
+    let output = match target {
+        "-" => Output::Stdout,
+        "%" => Output::Stderr,
+        filename => Output::File(File::create(filename)
+            .map_err(|_| format!("failed to create trace file: {}", filename))?),
+    };
+
+    let _ = TRACE.set(Trace {
+        mode,
+        time: args.trace_time,
+        state: Mutex::new(State {
+            output,
+            connections: Vec::new(),
+            request_pending: false,
+            new_connection: false,
+        }),
+    });
+    Ok(())
+}
+
+/// Returns true, if verbose output or a trace dump is enabled.
+pub fn is_enabled() -> bool {
+    TRACE.get().is_some()
+}
+
+impl Trace {
+    fn write(&self, state: &mut State, text: &str) {
+        if self.time {
+            state.output.write(&format!("{} {}", format_time(SystemTime::now()), text));
+        }
+        else {
+            state.output.write(text);
+        }
+    }
+
+    fn info(&self, state: &mut State, text: &str) {
+        let prefix = if self.mode == Mode::Verbose { "* " } else { "== Info: " };
+        for line in text.lines() {
+            self.write(state, &format!("{}{}\n", prefix, line));
+        }
+    }
+
+    /// Writes lines prefixed by '> ' or '< ' (verbose output only).
+    fn lines(&self, state: &mut State, prefix: &str, data: &[u8]) {
+        if self.mode != Mode::Verbose {
+            return;
+        }
+        let text = String::from_utf8_lossy(data);
+        let text = text.strip_suffix("\r\n").unwrap_or(&text);
+        for line in text.split("\r\n") {
+            let line = if line.is_empty() { String::from(prefix.trim_end()) } else { format!("{}{}", prefix, line) };
+            self.write(state, &(line + "\n"));
+        }
+    }
+
+    fn dump(&self, state: &mut State, label: &str, data: &[u8]) {
+        let dump = match self.mode {
+            Mode::Verbose => { return; },
+            Mode::Hex => hex_dump(data),
+            Mode::Ascii => ascii_dump(data),
+        };
+        self.write(state, &format!("{}, {} bytes (0x{:x})\n", label, data.len(), data.len()));
+        state.output.write(&dump);
+    }
+}
+
+fn with_trace<F: FnOnce(&Trace, &mut State)>(f: F) {
+    if let Some(trace) = TRACE.get() {
+        let mut state = trace.state.lock().unwrap_or_else(|err| err.into_inner());
+        f(trace, &mut state);
+    }
+}
+
+/// Writes an informational message.
+pub fn trace_info(text: &str) {
+    with_trace(|trace, state| trace.info(state, text));
+}
+
+/// Writes the request line and headers of a request about to be sent.
+pub fn trace_request(request: &Request) {
+    with_trace(|trace, state| {
+        state.request_pending = true;
+
+        let url = request.url();
+        let mut target = String::from(url.path());
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let mut data = format!("{} {} HTTP/{}\r\n", request.method(), target, format_version(request.version())).into_bytes();
+        for (name, value) in request.headers() {
+            data.extend_from_slice(name.as_str().as_bytes());
+            data.extend_from_slice(b": ");
+            data.extend_from_slice(value.as_bytes());
+            data.extend_from_slice(b"\r\n");
+        }
+        data.extend_from_slice(b"\r\n");
+        trace.lines(state, "> ", &redact(&data));
+    });
+}
+
+/// Writes the status line and headers of a received response.
+/// The server certificate is described for responses received on a new connection.
+pub fn trace_response(response: &Response) {
+    with_trace(|trace, state| {
+        if state.new_connection {
+            state.new_connection = false;
+            if let Some(addr) = response.remote_addr() {
+                trace.info(state, &format!("Connected to {} ({}) port {}",
+                    response.url().host_str().unwrap_or_default(), addr.ip(), addr.port()));
+            }
+            if let Some(text) = response.extensions().get::<TlsInfo>()
+                .and_then(|info| info.peer_certificate())
+                .and_then(describe_certificate) {
+                trace.info(state, &text);
+            }
+        }
+
+        let data = format_headers(response.version(), response.status(), get_reason(response), response.headers());
+        trace.lines(state, "< ", &redact(&data));
+    });
+}
+
+/// Parses a message of reqwest's connection logging (see WIRE_TARGET),
+/// e.g. "0a1b2c3d write: b\"GET / HTTP/1.1\\r\\n...\"".
+/// Returns the connection id, true for sent data and the data.
+fn parse_wire_message(message: &str) -> Option<(u32, bool, Vec<u8>)> {
+    let (id, rest) = message.split_once(' ')?;
+    let id = u32::from_str_radix(id, 16).ok()?;
+    let (is_write, data) = if let Some(data) = rest.strip_prefix("read: ") {
+        (false, data)
+    }
+    else if let Some(data) = rest.strip_prefix("write: ").or_else(|| rest.strip_prefix("write (vectored): ")) {
+        (true, data)
+    }
+    else {
+        return None;
+    };
+    Some((id, is_write, unescape(data)))
+}
+
+/// Processes a message of reqwest's connection logging.
+pub fn trace_wire(message: &str) {
+    let (id, is_write, data) = match parse_wire_message(message) {
+        Some(value) => value,
+        None => { return; }
+    };
+
+    with_trace(|trace, state| {
+        let number = state.connections.iter().position(|connection| *connection == id);
+        let number = match number {
+            Some(number) => {
+                if is_write && state.request_pending {
+                    trace.info(state, &format!("Re-using existing connection #{}", number));
+                }
+                number
+            },
+            None => {
+                state.connections.push(id);
+                state.new_connection = true;
+                let number = state.connections.len() - 1;
+                trace.info(state, &format!("Established connection #{}", number));
+                number
+            }
+        };
+        if is_write {
+            state.request_pending = false;
+        }
+
+        if is_write {
+            trace.dump(state, &format!("=> Send data (connection #{})", number), &redact(&data));
+        }
+        else {
+            trace.dump(state, &format!("<= Recv data (connection #{})", number), &data);
+        }
+    });
+}
+
+/// Removes the password of a URL, e.g. a proxy URL.
+pub fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) => {
+            if url.password().is_some() {
+                let _ = url.set_password(Some("[redacted]"));
+            }
+            url.to_string()
+        },
+        Err(_) => String::from(url),
+    }
+}
+
+fn describe_certificate(der: &[u8]) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let subject = format_name(cert.subject());
+    let issuer = format_name(cert.issuer());
+    Some(format!("Server certificate:\n subject: {}\n start date: {}\n expire date: {}\n issuer: {}",
+        subject, format_cert_time(&cert.validity().not_before), format_cert_time(&cert.validity().not_after), issuer))
+}
+
+/// Replaces the values of headers carrying credentials, including the token sent to
+/// the proxy bridge.
+fn redact(data: &[u8]) -> Cow<'_, [u8]> {
+    let is_sensitive = |line: &[u8]| {
+        SENSITIVE_HEADERS.iter().chain([&BRIDGE_TOKEN_HEADER]).any(|name| {
+            line.len() > name.len()
+                && line[..name.len()].eq_ignore_ascii_case(name.as_bytes())
+                && line[name.len()] == b':'
+        })
+    };
+    if !data.split(|c| *c == b'\n').any(is_sensitive) {
+        return Cow::Borrowed(data);
+    }
+
+    let mut result = Vec::with_capacity(data.len());
+    for (i, line) in data.split(|c| *c == b'\n').enumerate() {
+        if i > 0 {
+            result.push(b'\n');
+        }
+        if is_sensitive(line) {
+            let name_len = line.iter().position(|c| *c == b':').unwrap_or(line.len());
+            result.extend_from_slice(&line[..name_len]);
+            result.extend_from_slice(b": [redacted]");
+            if line.ends_with(b"\r") {
+                result.push(b'\r');
+            }
+        }
+        else {
+            result.extend_from_slice(line);
+        }
+    }
+    Cow::Owned(result)
+}
+
+/// Reverts the escaping of reqwest's connection logging, e.g. b"a\r\n\x00".
+/// Vectored writes are logged as consecutive literals, e.g. b"a"b"b".
+fn unescape(text: &str) -> Vec<u8> {
+    let text = text.strip_prefix("b\"").unwrap_or(text);
+
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 1 < bytes.len() {
+            match bytes[i + 1] {
+                b'n' => { result.push(b'\n'); i += 2; },
+                b'r' => { result.push(b'\r'); i += 2; },
+                b't' => { result.push(b'\t'); i += 2; },
+                b'0' => { result.push(0); i += 2; },
+                b'x' if i + 4 <= bytes.len() => {
+                    let hex = std::str::from_utf8(&bytes[i + 2..i + 4]).unwrap_or_default();
+                    match u8::from_str_radix(hex, 16) {
+                        Ok(value) => { result.push(value); i += 4; },
+                        Err(_) => { result.push(b'\\'); i += 1; }
+                    }
+                },
+                other => { result.push(other); i += 2; }
+            }
+        }
+        else if bytes[i] == b'"' {
+            // quotes within a literal are escaped, so this ends the literal
+            i += 1;
+            if bytes[i..].starts_with(b"b\"") {
+                i += 2;
+            }
+        }
+        else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+fn printable(c: u8) -> char {
+    if (0x20..0x7f).contains(&c) { c as char } else { '.' }
+}
+
+/// Formats data as hex dump, e.g. "0000: 47 45 54 GET".
+fn hex_dump(data: &[u8]) -> String {
+    let mut result = String::new();
+    for (i, chunk) in data.chunks(HEX_WIDTH).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|c| format!("{:02x}", c)).collect();
+        let ascii: String = chunk.iter().map(|c| printable(*c)).collect();
+        result.push_str(&format!("{:04x}: {:<width$} {}\n", i * HEX_WIDTH, hex.join(" "), ascii, width = HEX_WIDTH * 3 - 1));
+    }
+    result
+}
+
+/// Formats data as text; lines are split at CRLF and after 64 characters.
+fn ascii_dump(data: &[u8]) -> String {
+    let mut result = String::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let mut len = rest.len().min(ASCII_WIDTH);
+        let mut skip = 0;
+        if let Some(pos) = rest[..len].windows(2).position(|window| window == b"\r\n") {
+            len = pos;
+            skip = 2;
+        }
+        else if rest.len() > len && rest[len - 1] == b'\r' && rest[len] == b'\n' {
+            len -= 1;
+            skip = 2;
+        }
+        let line: String = rest[..len].iter().map(|c| printable(*c)).collect();
+        result.push_str(&format!("{:04x}: {}\n", offset, line));
+        offset += len + skip;
+    }
+    result
+}
+
+/// Formats the time of day (UTC) as HH:MM:SS.ffffff.
+fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() % 86400;
+    format!("{:02}:{:02}:{:02}.{:06}", seconds / 3600, (seconds / 60) % 60, seconds % 60, since_epoch.subsec_micros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(b"GET / HTTP/1.1\r\n\t\0\x8f\"\\".to_vec(), unescape("b\"GET / HTTP/1.1\\r\\n\\t\\0\\x8f\\\"\\\\\""));
+    }
+
+    #[test]
+    fn test_parse_wire_message() {
+        // format of reqwest 0.12.28 (connect.rs, verbose module)
+        assert_eq!(Some((0x0a1b2c3d, true, b"GET / HTTP/1.1\r\n".to_vec())),
+            parse_wire_message("0a1b2c3d write: b\"GET / HTTP/1.1\\r\\n\""));
+        assert_eq!(Some((1, true, b"abc\"d".to_vec())), parse_wire_message("00000001 write (vectored): b\"ab\"b\"c\\\"d\""));
+        assert_eq!(Some((1, false, b"HTTP/1.1 200 OK".to_vec())), parse_wire_message("00000001 read: b\"HTTP/1.1 200 OK\""));
+        assert_eq!(None, parse_wire_message("00000001 shutdown"));
+        assert_eq!(None, parse_wire_message("tunneling HTTPS over proxy"));
+    }
+
+    #[test]
+    fn test_redact() {
+        let data = b"GET / HTTP/1.1\r\nauthorization: Basic Zm9vOmJhcg==\r\nAccept: */*\r\nCookie: a=b\r\n\r\n";
+        assert_eq!(b"GET / HTTP/1.1\r\nauthorization: [redacted]\r\nAccept: */*\r\nCookie: [redacted]\r\n\r\n".as_ref(), redact(data).as_ref());
+
+        let data = b"CONNECT example.com:443 HTTP/1.1\r\nx-fetch-bridge: 0123abcd\r\n\r\n";
+        assert_eq!(b"CONNECT example.com:443 HTTP/1.1\r\nx-fetch-bridge: [redacted]\r\n\r\n".as_ref(), redact(data).as_ref());
+        assert_eq!(b"Accept: */*\r\n".as_ref(), redact(b"Accept: */*\r\n").as_ref());
+    }
+
+    #[test]
+    fn test_dumps() {
+        assert_eq!("0000: 47 45 54 0d 0a                                  GET..\n", hex_dump(b"GET\r\n"));
+        assert_eq!("0000: GET / HTTP/1.1\n0010: Accept: */*\n001d: .\n", ascii_dump(b"GET / HTTP/1.1\r\nAccept: */*\r\n\x01"));
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!("01:01:01.000500", format_time(UNIX_EPOCH + Duration::from_micros(3_661_000_500)));
+    }
+}
@@ -0,0 +1,205 @@
+use log::warn;
+use serde_json::{json, Value};
+use std::io::{stderr, stdout, Read, Write};
+use std::sync::OnceLock;
+
+use crate::args::Args;
+use crate::certinfo::format_tls_session;
+use crate::metrics::{get_metrics, update_metrics, Metrics};
+
+static FORMAT: OnceLock<String> = OnceLock::new();
+
+/// Loads the format of --write-out; '@file' reads the format from file, '@-' from stdin.
+pub fn init_write_out(args: &Args) -> Result<(), String> {
+    let format = match &args.write_out {
+        None => { return Ok(()); },
+        Some(format) => format,
+    };
+
+    let format = match format.strip_prefix('@') {
+        Some("-") => {
+            let mut format = String::new();
+            std::io::stdin().read_to_string(&mut format)
+                .map_err(|_| String::from("failed to read write-out format from stdin"))?;
+            format
+        },
+        Some(filename) => {
+            std::fs::read_to_string(filename)
+                .map_err(|_| format!("failed to read write-out format from file: {}", filename))?
+        },
+        None => format.clone(),
+    };
+
+    let _ = FORMAT.set(format);
+    Ok(())
+}
+
+/// Variables requiring the TLS session of the connections to be recorded.
+const TLS_SESSION_VARIABLES: [&str; 2] = ["%{certs}", "%{num_certs}"];
+
+/// Returns true, if the format of --write-out uses the TLS session (e.g. %{certs}).
+pub fn uses_tls_session() -> bool {
+    FORMAT.get().is_some_and(|format| TLS_SESSION_VARIABLES.iter().any(|variable| format.contains(variable)))
+}
+
+/// Writes the output of --write-out (if specified).
+pub fn write_out(exit_code: i32) {
+    if let Some(format) = FORMAT.get() {
+        update_metrics(|metrics| metrics.exitcode = exit_code);
+        let metrics = get_metrics();
+
+        let mut stdout = stdout();
+        let mut stderr = stderr();
+        render(format, &metrics, &mut stdout, &mut stderr);
+        let _ = stdout.flush();
+    }
+}
+
+fn render(format: &str, metrics: &Metrics, stdout: &mut dyn Write, stderr: &mut dyn Write) {
+    let mut to_stderr = false;
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' if chars.peek() == Some(&'%') => {
+                chars.next();
+                text.push('%');
+            },
+            '%' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                match name.as_str() {
+                    "stdout" | "stderr" => {
+                        let out: &mut dyn Write = if to_stderr { &mut *stderr } else { &mut *stdout };
+                        let _ = out.write_all(text.as_bytes());
+                        text.clear();
+                        to_stderr = name == "stderr";
+                    },
+                    _ => match get_variable(&name, metrics) {
+                        Some(value) => { text.push_str(&value); },
+                        None => { warn!("unknown --write-out variable: '{}'", name); }
+                    }
+                }
+            },
+            '\\' => {
+                match chars.next() {
+                    Some('n') => { text.push('\n'); },
+                    Some('r') => { text.push('\r'); },
+                    Some('t') => { text.push('\t'); },
+                    Some('\\') => { text.push('\\'); },
+                    Some(other) => { text.push('\\'); text.push(other); },
+                    None => { text.push('\\'); }
+                }
+            },
+            _ => { text.push(c); }
+        }
+    }
+
+    let out: &mut dyn Write = if to_stderr { stderr } else { stdout };
+    let _ = out.write_all(text.as_bytes());
+}
+
+fn format_verified(verified: Option<bool>) -> String {
+    match verified {
+        Some(verified) => verified.to_string(),
+        None => String::new(),
+    }
+}
+
+fn get_variable(name: &str, metrics: &Metrics) -> Option<String> {
+    let value = match name {
+        "certs" => format_tls_session(metrics),
+        "content_type" => metrics.content_type.clone(),
+        "errormsg" => metrics.errormsg.clone(),
+        "exitcode" => metrics.exitcode.to_string(),
+        "filename_effective" => metrics.filename_effective.clone(),
+        "http_code" | "response_code" => format!("{:03}", metrics.http_code),
+        "http_version" => metrics.http_version.clone(),
+        "json" => to_json(metrics).to_string(),
+        "md5" => metrics.md5.clone(),
+        "num_certs" => metrics.certs.len().to_string(),
+        "num_redirects" => metrics.num_redirects.to_string(),
+        "remote_ip" => metrics.remote_ip.clone(),
+        "remote_port" => metrics.remote_port.to_string(),
+        "sha256" => metrics.sha256.clone(),
+        "size_download" => metrics.size_download.to_string(),
+        "speed_download" => metrics.speed_download().to_string(),
+        "time_starttransfer" => format!("{:.6}", metrics.time_starttransfer),
+        "time_total" => format!("{:.6}", metrics.time_total()),
+        "url" => metrics.url.clone(),
+        "url_effective" => metrics.url_effective.clone(),
+        "verified" => format_verified(metrics.verified),
+        _ => { return None; }
+    };
+    Some(value)
+}
+
+fn to_json(metrics: &Metrics) -> Value {
+    json!({
+        "content_type": metrics.content_type,
+        "errormsg": metrics.errormsg,
+        "exitcode": metrics.exitcode,
+        "filename_effective": metrics.filename_effective,
+        "http_code": metrics.http_code,
+        "http_version": metrics.http_version,
+        "md5": metrics.md5,
+        "num_redirects": metrics.num_redirects,
+        "remote_ip": metrics.remote_ip,
+        "remote_port": metrics.remote_port,
+        "response_code": metrics.http_code,
+        "sha256": metrics.sha256,
+        "size_download": metrics.size_download,
+        "speed_download": metrics.speed_download(),
+        "time_starttransfer": metrics.time_starttransfer,
+        "time_total": metrics.time_total(),
+        "url": metrics.url,
+        "url_effective": metrics.url_effective,
+        "verified": metrics.verified,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_to_string(format: &str, metrics: &Metrics) -> (String, String) {
+        let mut stdout: Vec<u8> = Vec::new();
+        let mut stderr: Vec<u8> = Vec::new();
+        render(format, metrics, &mut stdout, &mut stderr);
+        (String::from_utf8(stdout).unwrap(), String::from_utf8(stderr).unwrap())
+    }
+
+    #[test]
+    fn test_render() {
+        let mut metrics = get_metrics();
+        metrics.http_code = 200;
+        metrics.url_effective = String::from("https://example.com/");
+        metrics.size_download = 42;
+        metrics.verified = Some(true);
+
+        let (out, _) = render_to_string("%{http_code} %{size_download} %{url_effective}\\n", &metrics);
+        assert_eq!("200 42 https://example.com/\n", out);
+
+        let (out, _) = render_to_string("100%% %{verified}", &metrics);
+        assert_eq!("100% true", out);
+
+        let (out, err) = render_to_string("a%{stderr}b%{stdout}c", &metrics);
+        assert_eq!("ac", out);
+        assert_eq!("b", err);
+
+        let (out, _) = render_to_string("%{unknown}", &metrics);
+        assert_eq!("", out);
+    }
+
+    #[test]
+    fn test_render_json() {
+        let mut metrics = get_metrics();
+        metrics.http_code = 404;
+        metrics.verified = None;
+
+        let (out, _) = render_to_string("%{json}", &metrics);
+        let value: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(404, value["http_code"]);
+        assert_eq!(Value::Null, value["verified"]);
+    }
+}
@@ -1,18 +1,126 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
 use std::{
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
+    io::{prelude::*, BufReader, ErrorKind},
+    net::{Ipv4Addr, Ipv6Addr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
 };
 
+/// Credentials accepted by the SOCKS5 proxy.
+const SOCKS_USER: &[u8] = b"user";
+const SOCKS_PASSWORD: &[u8] = b"secret";
+
+/// Host name which is only known to the SOCKS proxy (to test remote DNS).
+const SOCKS_HOST: &str = "socks.test";
+
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+    // SOCKS5 proxy
+    thread::spawn(|| serve("127.0.0.1:7879", handle_socks));
+
+    // SOCKS4 proxy (SOCKS4a)
+    thread::spawn(|| serve("127.0.0.1:7882", handle_socks4));
+
+    // HTTPS proxy (certificate signed by proxy-ca.pem)
+    let config = create_tls_config(false);
+    thread::spawn(move || serve_tls("127.0.0.1:7880", config));
+
+    // HTTPS proxy requiring client certificates
+    let config = create_tls_config(true);
+    thread::spawn(move || serve_tls("127.0.0.1:7881", config));
+
+    // HTTP proxy
+    serve("127.0.0.1:7878", handle_connection);
+}
+
+fn serve(address: &str, handler: fn(TcpStream)) {
+    let listener = TcpListener::bind(address).unwrap();
+
+    for stream in listener.incoming() {
+        let stream = stream.unwrap();
+
+        thread::spawn(move || handler(stream));
+    }
+}
+
+fn serve_tls(address: &str, config: Arc<ServerConfig>) {
+    let listener = TcpListener::bind(address).unwrap();
 
     for stream in listener.incoming() {
         let stream = stream.unwrap();
+        let config = config.clone();
 
-        handle_connection(stream);
+        thread::spawn(move || handle_tls(stream, config));
     }
 }
 
+fn create_tls_config(client_auth: bool) -> Arc<ServerConfig> {
+    let cert = CertificateDer::from_pem_slice(include_bytes!("cert.pem")).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(include_bytes!("key.pem")).unwrap();
+
+    let builder = ServerConfig::builder();
+    let builder = if client_auth {
+        let client_ca = CertificateDer::from_pem_slice(include_bytes!("../../test-server/src/client-ca.pem")).unwrap();
+        let mut roots = RootCertStore::empty();
+        roots.add(client_ca).unwrap();
+        builder.with_client_cert_verifier(WebPkiClientVerifier::builder(Arc::new(roots)).build().unwrap())
+    }
+    else {
+        builder.with_no_client_auth()
+    };
+    Arc::new(builder.with_single_cert(vec![cert], key).unwrap())
+}
+
+/// Terminates TLS and passes the connection to the HTTP proxy.
+fn handle_tls(stream: TcpStream, config: Arc<ServerConfig>) {
+    let Ok(connection) = ServerConnection::new(config) else {
+        return;
+    };
+    let Ok(mut upstream) = TcpStream::connect("127.0.0.1:7878") else {
+        return;
+    };
+
+    // both directions are served by one thread, since the TLS stream cannot be split
+    let timeout = Some(Duration::from_millis(10));
+    let _ = stream.set_read_timeout(timeout);
+    let _ = upstream.set_read_timeout(timeout);
+    let mut tls = StreamOwned::new(connection, stream);
+    let mut buffer = [0u8; 16 * 1024];
+    loop {
+        match tls.read(&mut buffer) {
+            Ok(0) => { break; },
+            Ok(n) => {
+                if upstream.write_all(&buffer[..n]).is_err() {
+                    break;
+                }
+            },
+            Err(err) if is_timeout(&err) => {},
+            Err(_) => { break; }
+        }
+
+        match upstream.read(&mut buffer) {
+            Ok(0) => {
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+                break;
+            },
+            Ok(n) => {
+                if tls.write_all(&buffer[..n]).and_then(|_| tls.flush()).is_err() {
+                    break;
+                }
+            },
+            Err(err) if is_timeout(&err) => {},
+            Err(_) => { break; }
+        }
+    }
+}
+
+fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
 fn handle_connection(mut stream: TcpStream) {
     let buf_reader = BufReader::new(&stream);
     let http_request: Vec<_> = buf_reader
@@ -23,8 +131,21 @@ fn handle_connection(mut stream: TcpStream) {
 
     println!("{:?}", http_request);
 
+    let request_line = http_request.first().cloned().unwrap_or_default();
+    if let Some(target) = request_line.strip_prefix("CONNECT ") {
+        let target = target.split(' ').next().unwrap_or_default();
+        tunnel(stream, target, &http_request);
+        return;
+    }
+
+    // requests to /headers echo the request headers received by the proxy
     let status_line = "HTTP/1.1 200 OK";
-    let contents = "proxy-response";
+    let contents = if request_line.split(' ').nth(1).is_some_and(|url| url.ends_with("/headers")) {
+        http_request[1..].join("\n")
+    }
+    else {
+        String::from("proxy-response")
+    };
     let length = contents.len();
 
     let response =
@@ -32,3 +153,176 @@ fn handle_connection(mut stream: TcpStream) {
 
     stream.write_all(response.as_bytes()).unwrap();
 }
+
+/// Tunnels the connection to the target (CONNECT).
+/// Requests with a "X-Reject: yes" header are rejected with 403.
+fn tunnel(mut stream: TcpStream, target: &str, http_request: &[String]) {
+    if http_request.iter().any(|line| line.eq_ignore_ascii_case("x-reject: yes")) {
+        let _ = stream.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n");
+        return;
+    }
+
+    let Ok(upstream) = TcpStream::connect(target) else {
+        let _ = stream.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n");
+        return;
+    };
+    stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").unwrap();
+
+    relay(stream, upstream);
+}
+
+/// Forwards data between client and upstream until both are closed.
+fn relay(mut stream: TcpStream, upstream: TcpStream) {
+    let mut client_reader = stream.try_clone().unwrap();
+    let mut upstream_writer = upstream.try_clone().unwrap();
+    let forward = thread::spawn(move || {
+        let _ = std::io::copy(&mut client_reader, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(std::net::Shutdown::Write);
+    });
+    let mut upstream_reader = upstream;
+    let _ = std::io::copy(&mut upstream_reader, &mut stream);
+    let _ = stream.shutdown(std::net::Shutdown::Write);
+    let _ = forward.join();
+}
+
+fn handle_socks(mut stream: TcpStream) {
+    let target = socks5_handshake(&mut stream);
+    println!("SOCKS5 {:?}", target);
+
+    let upstream = target.and_then(|(host, port)| {
+        let host = if host == SOCKS_HOST { String::from("localhost") } else { host };
+        TcpStream::connect((host.as_str(), port)).ok()
+    });
+
+    let status = if upstream.is_some() { 0 } else { 1 };
+    if stream.write_all(&[5, status, 0, 1, 0, 0, 0, 0, 0, 0]).is_err() {
+        return;
+    }
+
+    if let Some(upstream) = upstream {
+        relay(stream, upstream);
+    }
+}
+
+fn handle_socks4(mut stream: TcpStream) {
+    let target = socks4_handshake(&mut stream);
+    println!("SOCKS4 {:?}", target);
+
+    let upstream = target.and_then(|(host, port)| {
+        let host = if host == SOCKS_HOST { String::from("localhost") } else { host };
+        TcpStream::connect((host.as_str(), port)).ok()
+    });
+
+    let status = if upstream.is_some() { 90 } else { 91 };
+    if stream.write_all(&[0, status, 0, 0, 0, 0, 0, 0]).is_err() {
+        return;
+    }
+
+    if let Some(upstream) = upstream {
+        relay(stream, upstream);
+    }
+}
+
+/// Reads the SOCKS4 CONNECT request and returns its target (SOCKS4a: the host name
+/// follows the user ID, if the address is 0.0.0.x). Requests followed by further
+/// data before the reply was sent are malformed and rejected.
+fn socks4_handshake(stream: &mut TcpStream) -> Option<(String, u16)> {
+    let mut request = [0u8; 8];
+    stream.read_exact(&mut request).ok()?;
+    if request[0] != 4 || request[1] != 1 {
+        return None;
+    }
+    let port = u16::from_be_bytes([request[2], request[3]]);
+    let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+
+    let user_id = read_until_nul(stream)?;
+    println!("SOCKS4 user ID {:?}", String::from_utf8_lossy(&user_id));
+    let host = if ip.octets()[..3] == [0, 0, 0] && ip.octets()[3] != 0 {
+        String::from_utf8(read_until_nul(stream)?).ok()?
+    }
+    else {
+        ip.to_string()
+    };
+
+    stream.set_read_timeout(Some(Duration::from_millis(50))).ok()?;
+    let mut extra = [0u8; 1];
+    let is_malformed = stream.read(&mut extra).is_ok_and(|count| count > 0);
+    stream.set_read_timeout(None).ok()?;
+    if is_malformed { None } else { Some((host, port)) }
+}
+
+/// Reads a string terminated by NUL.
+fn read_until_nul(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte).ok()?;
+        if byte[0] == 0 {
+            return Some(data);
+        }
+        data.push(byte[0]);
+    }
+}
+
+/// Performs the SOCKS5 authentication and returns the target of the CONNECT request.
+/// Username/password authentication is used if offered by the client.
+fn socks5_handshake(stream: &mut TcpStream) -> Option<(String, u16)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).ok()?;
+    if header[0] != 5 {
+        return None;
+    }
+    let count = [header[1]];
+    let mut methods = vec![0u8; count[0] as usize];
+    stream.read_exact(&mut methods).ok()?;
+
+    if methods.contains(&2) {
+        stream.write_all(&[5, 2]).ok()?;
+        let mut version = [0u8; 1];
+        stream.read_exact(&mut version).ok()?;
+        let user = read_bytes(stream)?;
+        let password = read_bytes(stream)?;
+        let valid = user == SOCKS_USER && password == SOCKS_PASSWORD;
+        stream.write_all(&[1, if valid { 0 } else { 1 }]).ok()?;
+        if !valid {
+            return None;
+        }
+    }
+    else if methods.contains(&0) {
+        stream.write_all(&[5, 0]).ok()?;
+    }
+    else {
+        stream.write_all(&[5, 0xff]).ok()?;
+        return None;
+    }
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).ok()?;
+    let host = match request[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).ok()?;
+            Ipv4Addr::from(ip).to_string()
+        },
+        3 => String::from_utf8(read_bytes(stream)?).ok()?,
+        4 => {
+            let mut ip = [0u8; 16];
+            stream.read_exact(&mut ip).ok()?;
+            Ipv6Addr::from(ip).to_string()
+        },
+        _ => { return None; }
+    };
+    let mut port = [0u8; 2];
+    stream.read_exact(&mut port).ok()?;
+
+    if request[1] == 1 { Some((host, u16::from_be_bytes(port))) } else { None }
+}
+
+/// Reads a string prefixed by its length.
+fn read_bytes(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut length = [0u8; 1];
+    stream.read_exact(&mut length).ok()?;
+    let mut data = vec![0u8; length[0] as usize];
+    stream.read_exact(&mut data).ok()?;
+    Some(data)
+}
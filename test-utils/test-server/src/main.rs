@@ -1,7 +1,9 @@
 use axum::{
+    body::Body,
     extract::Multipart,
     extract::Request,
-    http::header::USER_AGENT,
+    http::header::{ACCEPT_RANGES, AUTHORIZATION, CONTENT_ENCODING, CONTENT_RANGE, ETAG, HOST, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, LOCATION, RANGE, USER_AGENT, WWW_AUTHENTICATE},
+    routing::any,
     routing::get,
     routing::post,
     routing::put,
@@ -12,7 +14,24 @@ use axum::{
 };
 
 use axum_server::tls_rustls::RustlsConfig;
-use std::{thread, time::Duration, net::SocketAddr};
+use bytes::{Buf, Bytes};
+use h3::server::RequestStream;
+use quinn::crypto::rustls::QuicServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::future::IntoFuture;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use md5::{Digest, Md5};
+use tower::ServiceExt;
+use std::{thread, time::{Duration, UNIX_EPOCH}, net::SocketAddr};
+
+// number of requests to /downgraded (redirect target of /redirect_http)
+static DOWNGRADED_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+// number of requests to /ranges_changing, used as version of the resource
+static RANGES_VERSION: AtomicUsize = AtomicUsize::new(0);
 
 #[tokio::main]
 async fn main() {
@@ -26,6 +45,22 @@ async fn main() {
         .route("/delete", delete(do_delete))
         .route("/user_agent", get(get_user_agent))
         .route("/error", get(get_error))
+        .route("/ranges", get(get_ranges))
+        .route("/ranges_changing", get(get_ranges_changing))
+        .route("/compressed", get(get_compressed))
+        .route("/redirect", get(redirect))
+        .route("/cached", get(get_cached))
+        .route("/basic", get(get_basic))
+        .route("/digest", get(get_digest))
+        .route("/auth_header", get(get_auth_header))
+        .route("/redirect_other_host", get(redirect_other_host))
+        .route("/redirect_method", any(redirect_method))
+        .route("/redirect_http", get(redirect_http))
+        .route("/downgraded", get(downgraded))
+        .route("/downgraded_count", get(downgraded_count))
+        .route("/method", any(echo_method))
+        .route("/host", get(get_host))
+        .route("/version", get(get_version))
         ;
 
     // configure certificate and private key used by https
@@ -35,6 +70,39 @@ async fn main() {
         .await
         .unwrap();
 
+    // https server requiring client certificates (mTLS)
+    let mtls_app = Router::new()
+        .route("/", get(welcome_client));
+    let mtls_config = create_mtls_config();
+    let mtls_addr = SocketAddr::from(([127, 0, 0, 1], 9001));
+    tokio::spawn(axum_server::bind_rustls(mtls_addr, mtls_config)
+        .serve(mtls_app.into_make_service()));
+
+    // https servers stapling an OCSP response (good and revoked)
+    let ocsp_addr = SocketAddr::from(([127, 0, 0, 1], 9003));
+    tokio::spawn(axum_server::bind_rustls(ocsp_addr, create_ocsp_config(include_bytes!("ocsp-good.der")))
+        .serve(app.clone().into_make_service()));
+    let ocsp_addr = SocketAddr::from(([127, 0, 0, 1], 9004));
+    tokio::spawn(axum_server::bind_rustls(ocsp_addr, create_ocsp_config(include_bytes!("ocsp-revoked.der")))
+        .serve(app.clone().into_make_service()));
+
+    // http server (HTTP/1.1 and HTTP/2 with prior knowledge)
+    let http_addr = SocketAddr::from(([127, 0, 0, 1], 9002));
+    tokio::spawn(axum_server::bind(http_addr)
+        .serve(app.clone().into_make_service()));
+
+    // http servers on a unix socket and an abstract unix socket
+    let socket = std::env::temp_dir().join("fetch-test-server.sock");
+    let _ = std::fs::remove_file(&socket);
+    let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+    tokio::spawn(axum::serve(listener, app.clone()).into_future());
+    let listener = tokio::net::UnixListener::bind("\0fetch-test-server").unwrap();
+    tokio::spawn(axum::serve(listener, app.clone()).into_future());
+
+    // http/3 server (QUIC) on the UDP port of the https server
+    let h3_addr = SocketAddr::from(([127, 0, 0, 1], 9000));
+    tokio::spawn(serve_http3(h3_addr, app.clone()));
+
     // run https server
     let addr = SocketAddr::from(([127, 0, 0, 1], 9000));
     axum_server::bind_rustls(addr, config)
@@ -43,6 +111,84 @@ async fn main() {
         .unwrap();
 }
 
+fn create_mtls_config() -> RustlsConfig {
+    let cert = CertificateDer::from_pem_slice(include_bytes!("cert.pem")).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(include_bytes!("key.pem")).unwrap();
+    let client_ca = CertificateDer::from_pem_slice(include_bytes!("client-ca.pem")).unwrap();
+
+    let mut roots = RootCertStore::empty();
+    roots.add(client_ca).unwrap();
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots)).build().unwrap();
+
+    let config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    RustlsConfig::from_config(Arc::new(config))
+}
+
+fn create_ocsp_config(ocsp: &[u8]) -> RustlsConfig {
+    let cert = CertificateDer::from_pem_slice(include_bytes!("cert.pem")).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(include_bytes!("key.pem")).unwrap();
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert_with_ocsp(vec![cert], key, ocsp.to_vec())
+        .unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    RustlsConfig::from_config(Arc::new(config))
+}
+
+/// Serves the app via HTTP/3. Request and response bodies are buffered.
+async fn serve_http3(addr: SocketAddr, app: Router) {
+    let cert = CertificateDer::from_pem_slice(include_bytes!("cert.pem")).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(include_bytes!("key.pem")).unwrap();
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    config.alpn_protocols = vec![b"h3".to_vec()];
+    let config = QuicServerConfig::try_from(config).unwrap();
+    let endpoint = quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(config)), addr).unwrap();
+
+    while let Some(incoming) = endpoint.accept().await {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let Ok(connection) = incoming.await else { return };
+            let Ok(mut connection) = h3::server::Connection::new(h3_quinn::Connection::new(connection)).await else { return };
+            while let Ok(Some(resolver)) = connection.accept().await {
+                let app = app.clone();
+                tokio::spawn(async move {
+                    if let Ok((request, stream)) = resolver.resolve_request().await {
+                        let _ = serve_http3_request(app, request, stream).await;
+                    }
+                });
+            }
+        });
+    }
+}
+
+async fn serve_http3_request(app: Router, request: Request<()>, mut stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>)
+    -> Result<(), h3::error::StreamError> {
+    let mut body = Vec::new();
+    while let Some(mut data) = stream.recv_data().await? {
+        body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+    }
+
+    let (parts, _) = request.into_parts();
+    let response = app.oneshot(Request::from_parts(parts, Body::from(body))).await.unwrap();
+
+    let (parts, body) = response.into_parts();
+    stream.send_response(Response::from_parts(parts, ())).await?;
+    stream.send_data(axum::body::to_bytes(body, usize::MAX).await.unwrap()).await?;
+    stream.finish().await
+}
+
+async fn welcome_client() -> &'static str {
+    "Welcome, client!"
+}
+
 async fn welcome() -> &'static str {
     "Welcome!"
 }
@@ -85,9 +231,206 @@ async fn get_user_agent(request: Request) -> String {
     }
 }
 
+/// Returns the HTTP version of the request, e.g. "HTTP/2.0".
+async fn get_version(request: Request) -> String {
+    format!("{:?}", request.version())
+}
+
+/// Returns the Host header and the path of the request.
+async fn get_host(request: Request) -> String {
+    let host = request.headers().get(HOST).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    format!("{} {}", host, request.uri().path())
+}
+
 async fn get_error() -> Response {
     Response::builder()
         .status(500)
         .body("Something went wrong.".into())
         .unwrap()
-}
\ No newline at end of file
+}
+async fn get_ranges(request: Request) -> Response {
+    ranges_response(request, "\"ranges-v1\"")
+}
+
+// resource changing with every request (new ETag)
+async fn get_ranges_changing(request: Request) -> Response {
+    let version = RANGES_VERSION.fetch_add(1, Ordering::Relaxed);
+    ranges_response(request, &format!("\"ranges-v{}\"", version))
+}
+
+fn ranges_response(request: Request, etag: &str) -> Response {
+    let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+
+    // the range is ignored if the resource does not match If-Range
+    let unchanged = request.headers().get(IF_RANGE)
+        .is_none_or(|value| value.as_bytes() == etag.as_bytes());
+    let range = request.headers().get(RANGE)
+        .filter(|_| unchanged)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("bytes="))
+        .and_then(|value| value.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?)));
+
+    if let Some((start, end)) = range {
+        let end = end.min(data.len() - 1);
+        Response::builder()
+            .status(206)
+            .header(ACCEPT_RANGES, "bytes")
+            .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, data.len()))
+            .header(ETAG, etag)
+            .body(data[start..=end].to_vec().into())
+            .unwrap()
+    }
+    else {
+        Response::builder()
+            .status(200)
+            .header(ACCEPT_RANGES, "bytes")
+            .header(ETAG, etag)
+            .body(data.into())
+            .unwrap()
+    }
+}
+
+async fn get_compressed() -> Response {
+    // "Hello, compressed World!" (gzip)
+    let data: Vec<u8> = vec![
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+        0x51, 0x48, 0xce, 0xcf, 0x2d, 0x28, 0x4a, 0x2d, 0x2e, 0x4e, 0x4d, 0x51, 0x08, 0xcf, 0x2f, 0xca,
+        0x49, 0x51, 0x04, 0x00, 0x33, 0xb8, 0xff, 0x69, 0x18, 0x00, 0x00, 0x00];
+
+    Response::builder()
+        .status(200)
+        .header(CONTENT_ENCODING, "gzip")
+        .body(data.into())
+        .unwrap()
+}
+
+async fn redirect() -> Response {
+    Response::builder()
+        .status(302)
+        .header(LOCATION, "/")
+        .body("".into())
+        .unwrap()
+}
+
+async fn get_cached(request: Request) -> Response {
+    // Wed, 01 Jan 2025 00:00:00 GMT
+    let last_modified = UNIX_EPOCH + Duration::from_secs(1_735_689_600);
+    let etag = "\"v1\"";
+
+    let etag_matches = request.headers().get(IF_NONE_MATCH)
+        .map(|value| value.as_bytes() == etag.as_bytes())
+        .unwrap_or(false);
+    let not_modified = request.headers().get(IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .map(|since| since >= last_modified)
+        .unwrap_or(false);
+
+    let builder = Response::builder()
+        .header(ETAG, etag)
+        .header(LAST_MODIFIED, httpdate::fmt_http_date(last_modified));
+    if etag_matches || not_modified {
+        builder.status(304).body("".into()).unwrap()
+    }
+    else {
+        builder.status(200).body("Cached!".into()).unwrap()
+    }
+}
+
+fn unauthorized(challenge: &str) -> Response {
+    Response::builder()
+        .status(401)
+        .header(WWW_AUTHENTICATE, challenge)
+        .body("Unauthorized".into())
+        .unwrap()
+}
+
+async fn get_basic(request: Request) -> Response {
+    // fetch:secret
+    match request.headers().get(AUTHORIZATION) {
+        Some(value) if value == "Basic ZmV0Y2g6c2VjcmV0" => Response::new("Authenticated!".into()),
+        _ => unauthorized("Basic realm=\"test\""),
+    }
+}
+
+async fn get_digest(request: Request) -> Response {
+    const REALM: &str = "test";
+    const NONCE: &str = "dcd98b7102dd2f0e8b11d0f600bfb0c093";
+
+    let authorization = request.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Digest "))
+        .unwrap_or_default();
+    let params: Vec<(&str, &str)> = authorization.split(", ")
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key, value.trim_matches('"')))
+        .collect();
+    let get = |name: &str| params.iter().find(|(key, _)| *key == name).map(|(_, value)| *value).unwrap_or_default();
+
+    let hash = |data: String| hex(&Md5::digest(data.as_bytes()));
+    let ha1 = hash(format!("fetch:{}:secret", REALM));
+    let ha2 = hash(format!("GET:{}", get("uri")));
+    let expected = hash(format!("{}:{}:{}:{}:auth:{}", ha1, NONCE, get("nc"), get("cnonce"), ha2));
+
+    if get("username") == "fetch" && get("nonce") == NONCE && get("response") == expected {
+        Response::new("Authenticated!".into())
+    }
+    else {
+        unauthorized(&format!("Digest realm=\"{}\", qop=\"auth\", nonce=\"{}\", algorithm=MD5", REALM, NONCE))
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|c| format!("{:02x}", c)).collect()
+}
+
+async fn get_auth_header(request: Request) -> String {
+    request.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| String::from("none"))
+}
+
+async fn redirect_other_host() -> Response {
+    Response::builder()
+        .status(302)
+        .header(LOCATION, "https://127.0.0.1:9000/auth_header")
+        .body("".into())
+        .unwrap()
+}
+
+async fn redirect_http() -> Response {
+    Response::builder()
+        .status(302)
+        .header(LOCATION, "http://localhost:9002/downgraded")
+        .body("".into())
+        .unwrap()
+}
+
+async fn downgraded() -> &'static str {
+    DOWNGRADED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    "Downgraded!"
+}
+
+async fn downgraded_count() -> String {
+    DOWNGRADED_REQUESTS.load(Ordering::Relaxed).to_string()
+}
+
+async fn redirect_method(request: Request) -> Response {
+    // status is passed as query, e.g. /redirect_method?301
+    let status = request.uri().query()
+        .and_then(|query| query.parse::<u16>().ok())
+        .unwrap_or(302);
+    Response::builder()
+        .status(status)
+        .header(LOCATION, "/method")
+        .body("".into())
+        .unwrap()
+}
+
+async fn echo_method(request: Request) -> String {
+    let method = request.method().to_string();
+    let body = axum::body::to_bytes(request.into_body(), 1024).await.unwrap_or_default();
+    format!("{} {}", method, String::from_utf8_lossy(&body))
+}
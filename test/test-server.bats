@@ -116,3 +116,437 @@ teardown() {
         false
     fi
 }
+
+@test "download in segments" {
+    $FETCH -k https://localhost:9000/ranges -o ranges_single.tmp
+    $FETCH -k https://localhost:9000/ranges -o ranges_segments.tmp --segments 4
+    cmp ranges_single.tmp ranges_segments.tmp
+    rm ranges_single.tmp ranges_segments.tmp
+}
+
+@test "abort segmented download if the resource changes" {
+    run $FETCH -k https://localhost:9000/ranges_changing -o "$BATS_TEST_TMPDIR/ranges.tmp" --segments 4
+    [[ "$status" != "0" ]]
+    [[ "$output" == *"resource changed during download"* ]]
+    [[ "$output" != *"retry"* ]]
+    [[ ! -e "$BATS_TEST_TMPDIR/ranges.tmp" ]]
+}
+
+@test "decode compressed response (--compressed)" {
+    data=$($FETCH -k --compressed https://localhost:9000/compressed)
+    [[ "$data" == "Hello, compressed World!" ]]
+}
+
+@test "verify checksum of encoded data (--checksum-encoded)" {
+    SHA256_HASH=98fb32c142bd52c73440be70c7baaf06da9c928251737242a3ae697b53324a8b
+    data=$($FETCH -k --compressed --checksum-encoded --sha256 $SHA256_HASH https://localhost:9000/compressed)
+    [[ "$data" == "Hello, compressed World!" ]]
+}
+
+@test "fail on decoded size larger than max-filesize" {
+    if $FETCH -k --compressed --max-filesize 10 https://localhost:9000/compressed ; then
+        false
+    fi
+}
+
+@test "limit transfer rate (--limit-rate)" {
+    start=$(date +%s)
+    $FETCH -k https://localhost:9000/ranges -o ranges.tmp --limit-rate 50K
+    end=$(date +%s)
+    rm ranges.tmp
+    [[ $(( end - start )) -ge 1 ]]
+}
+
+@test "abort slow transfers (--speed-limit, --speed-time)" {
+    run $FETCH -k https://localhost:9000/slow_answer --speed-limit 1 --speed-time 2
+    [[ "$status" == "28" ]]
+}
+
+@test "don't show progress meter if stderr is no terminal" {
+    $FETCH -k https://localhost:9000/ranges -o ranges.tmp 2> stderr.tmp
+    size=$(stat -c %s stderr.tmp)
+    rm ranges.tmp stderr.tmp
+    [[ "$size" == "0" ]]
+}
+
+@test "write out transfer metrics (--write-out)" {
+    data=$($FETCH -k -L https://localhost:9000/redirect -o /dev/null -w '%{http_code} %{num_redirects} %{size_download} %{url_effective}')
+    [[ "$data" == "200 1 8 https://localhost:9000/" ]]
+}
+
+@test "write out checksum and verification result (--write-out)" {
+    SHA256_HASH=cc8fd9eb7f959b13f36a65625796d0b359ce5bce5551d159a3a920f41cb341ee
+    data=$($FETCH -k https://localhost:9000/ --sha256 $SHA256_HASH -o /dev/null -w '%{sha256} %{verified}')
+    [[ "$data" == "$SHA256_HASH true" ]]
+}
+
+@test "write json report (--report)" {
+    if $FETCH -k https://localhost:9000/ --sha256 invalid -o /dev/null --report json=report.tmp ; then
+        false
+    fi
+    report=$(cat report.tmp)
+    rm report.tmp
+    [[ "$report" == *'"status": 200'* ]]
+    [[ "$report" == *'"type": "checksum"'* ]]
+}
+
+@test "write json report on invalid CA certificate (--report)" {
+    if $FETCH --cacert missing.pem https://localhost:9000/ -o /dev/null --report json=report.tmp ; then
+        false
+    fi
+    report=$(cat report.tmp)
+    rm report.tmp
+    [[ "$report" == *'"type": "tls"'* ]]
+    [[ "$report" == *'failed to read CA certificate file'* ]]
+}
+
+@test "show headers only (--head)" {
+    data=$($FETCH -k -I https://localhost:9000/)
+    [[ "$data" == "HTTP/1.1 200 OK"* ]]
+    [[ "$data" != *"Welcome!"* ]]
+}
+
+@test "dump headers of all responses (--dump-header)" {
+    data=$($FETCH -k -L -D headers.tmp https://localhost:9000/redirect)
+    headers=$(cat headers.tmp)
+    rm headers.tmp
+    [[ "$data" == "Welcome!" ]]
+    [[ "$headers" == "HTTP/1.1 302 Found"*"HTTP/1.1 200 OK"* ]]
+}
+
+@test "trace data and redact credentials (--trace-ascii)" {
+    $FETCH -k -H "Authorization: Bearer secret" --trace-ascii trace.tmp https://localhost:9000/ -o /dev/null
+    trace=$(cat trace.tmp)
+    rm trace.tmp
+    [[ "$trace" == *"GET / HTTP/1.1"* ]]
+    [[ "$trace" == *"authorization: [redacted]"* ]]
+    [[ "$trace" != *"secret"* ]]
+}
+
+@test "skip download if ETag matches (--etag-compare)" {
+    $FETCH -k --etag-save etag.tmp -o cached.tmp https://localhost:9000/cached
+    echo "changed" > cached.tmp
+    $FETCH -k --etag-compare etag.tmp -o cached.tmp https://localhost:9000/cached
+    data=$(cat cached.tmp)
+    etag=$(cat etag.tmp)
+    rm cached.tmp etag.tmp
+    [[ "$data" == "changed" ]]
+    [[ "$etag" == '"v1"' ]]
+}
+
+@test "download if modified since (--time-cond, --remote-time)" {
+    $FETCH -k -z "Tue, 31 Dec 2024 00:00:00 GMT" -R -o cached.tmp https://localhost:9000/cached
+    data=$(cat cached.tmp)
+    mtime=$(stat -c %Y cached.tmp)
+    $FETCH -k -z cached.tmp -o cached.tmp -w '%{http_code}' https://localhost:9000/cached > status.tmp
+    status_code=$(cat status.tmp)
+    rm cached.tmp status.tmp
+    [[ "$data" == "Cached!" ]]
+    [[ "$mtime" == "1735689600" ]]
+    [[ "$status_code" == "304" ]]
+}
+
+@test "time condition as ISO date (--time-cond)" {
+    data=$($FETCH -k -z 2099-01-01 -o /dev/null -w '%{http_code}' https://localhost:9000/cached)
+    [[ "$data" == "304" ]]
+
+    run $FETCH -k -z yesterday https://localhost:9000/cached
+    [[ "$status" -ne 0 ]]
+    [[ "$output" == *"invalid --time-cond"* ]]
+}
+
+@test "basic authentication (--user)" {
+    data=$($FETCH -k -u fetch:secret https://localhost:9000/basic)
+    [[ "$data" == "Authenticated!" ]]
+}
+
+@test "digest authentication (--digest)" {
+    data=$($FETCH -k --digest -u fetch:secret https://localhost:9000/digest)
+    [[ "$data" == "Authenticated!" ]]
+}
+
+@test "bearer authentication (--oauth2-bearer)" {
+    data=$($FETCH -k --oauth2-bearer token https://localhost:9000/auth_header)
+    [[ "$data" == "Bearer token" ]]
+}
+
+@test "don't send credentials to other hosts on redirect" {
+    data=$($FETCH -k -L -u fetch:secret https://localhost:9000/redirect_other_host)
+    [[ "$data" == "none" ]]
+
+    data=$($FETCH -k -L --location-trusted -u fetch:secret https://localhost:9000/redirect_other_host)
+    [[ "$data" == "Basic ZmV0Y2g6c2VjcmV0" ]]
+}
+
+@test "don't send Authorization header of -H to other hosts on redirect" {
+    data=$($FETCH -k -L -H "Authorization: Bearer secret" https://localhost:9000/redirect_other_host)
+    [[ "$data" == "none" ]]
+
+    data=$($FETCH -k -L --location-trusted -H "Authorization: Bearer secret" https://localhost:9000/redirect_other_host)
+    [[ "$data" == "Bearer secret" ]]
+}
+
+@test "read credentials from netrc file (--netrc-file)" {
+    printf 'machine localhost\n  login fetch\n  password "secret"\n' > netrc.tmp
+    data=$($FETCH -k --netrc-file netrc.tmp https://localhost:9000/basic)
+    rm netrc.tmp
+    [[ "$data" == "Authenticated!" ]]
+}
+
+@test "fail without client certificate" {
+    run $FETCH -k https://localhost:9001/
+    [[ "$status" != "0" ]]
+}
+
+@test "client certificate and key (--cert, --key)" {
+    data=$($FETCH -k -E test/certs/client.pem --key test/certs/client.key https://localhost:9001/)
+    [[ "$data" == "Welcome, client!" ]]
+}
+
+@test "client certificate with encrypted key (--pass)" {
+    data=$($FETCH -k -E test/certs/client.pem --key test/certs/client-encrypted.key --pass secret https://localhost:9001/)
+    [[ "$data" == "Welcome, client!" ]]
+}
+
+@test "client certificate as PKCS#12 bundle (--cert)" {
+    data=$($FETCH -k -E test/certs/client.p12:secret https://localhost:9001/)
+    [[ "$data" == "Welcome, client!" ]]
+}
+
+@test "public key pinning (--pinnedpubkey)" {
+    pin=$(openssl x509 -in test-utils/test-server/src/cert.pem -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64)
+    data=$($FETCH -k --pinnedpubkey "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=;sha256//$pin" https://localhost:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    openssl x509 -in test-utils/test-server/src/cert.pem -pubkey -noout > pubkey.tmp
+    data=$($FETCH -k --pinnedpubkey pubkey.tmp https://localhost:9000/)
+    rm pubkey.tmp
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "fail if pinned public key does not match (exit code 90)" {
+    run $FETCH -k --pinnedpubkey "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=" https://localhost:9000/
+    [[ "$status" == "90" ]]
+}
+
+@test "refuse disabled protocol (--proto)" {
+    run $FETCH --proto =https http://localhost:9000/
+    [[ "$status" == "1" ]]
+
+    data=$($FETCH -k --proto -all,https https://localhost:9000/)
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "refuse disabled protocol on redirect (--proto-redir)" {
+    run $FETCH -k -L --proto-redir -all https://localhost:9000/redirect
+    [[ "$status" == "1" ]]
+
+    data=$($FETCH -k -L --proto-redir =https https://localhost:9000/redirect)
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "refuse redirect to http with --proto =https" {
+    before=$($FETCH http://localhost:9002/downgraded_count)
+    run $FETCH -k -L --proto =https https://localhost:9000/redirect_http
+    after=$($FETCH http://localhost:9002/downgraded_count)
+    [[ "$status" -ne 0 ]]
+    [[ "$output" == *'protocol "http" not allowed for redirects'* ]]
+    [[ "$before" == "$after" ]]
+}
+
+@test "restrict redirect hosts (--redirect-allow-host)" {
+    run $FETCH -k -L --redirect-allow-host example.com https://localhost:9000/redirect
+    [[ "$status" == "1" ]]
+
+    data=$($FETCH -k -L --redirect-allow-host localhost https://localhost:9000/redirect)
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "unlimited redirects (--max-redirs -1)" {
+    data=$($FETCH -k -L --max-redirs -1 https://localhost:9000/redirect)
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "keep POST on redirect (--post301, --post302, --post303)" {
+    data=$($FETCH -k -L -d data "https://localhost:9000/redirect_method?302")
+    [[ "$data" == "GET " ]]
+
+    for status in 301 302 303; do
+        data=$($FETCH -k -L -d data --post$status "https://localhost:9000/redirect_method?$status")
+        [[ "$data" == "POST data" ]]
+    done
+}
+
+@test "override host resolution (--resolve)" {
+    data=$($FETCH -k --resolve example.test:9000:127.0.0.1 https://example.test:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    # the certificate is verified for the host of the URL
+    data=$($FETCH --cacert test-utils/test-server/src/cert.pem --resolve localhost:9000:127.0.0.1 https://localhost:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    # entries only apply to their port
+    run $FETCH -k --resolve example.test:443:127.0.0.1 https://example.test:9000/
+    [[ "$status" != "0" ]]
+}
+
+@test "connect to another host and port (--connect-to)" {
+    data=$($FETCH -k --connect-to example.test:443:localhost:9000 https://example.test/)
+    [[ "$data" == "Welcome!" ]]
+
+    data=$($FETCH -k --connect-to example.test::localhost: https://example.test:9000/)
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "select address family (-4, -6)" {
+    data=$($FETCH -k -4 --resolve example.test:9000:::1,127.0.0.1 https://example.test:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    run $FETCH -k -6 --resolve example.test:9000:127.0.0.1 https://example.test:9000/
+    [[ "$status" != "0" ]]
+}
+
+@test "unix domain socket (--unix-socket, --abstract-unix-socket)" {
+    data=$($FETCH --unix-socket ${TMPDIR:-/tmp}/fetch-test-server.sock http://localhost/)
+    [[ "$data" == "Welcome!" ]]
+
+    # the URL provides Host header and path
+    data=$($FETCH --unix-socket ${TMPDIR:-/tmp}/fetch-test-server.sock http://example.test:8080/host)
+    [[ "$data" == "example.test:8080 /host" ]]
+
+    data=$($FETCH --abstract-unix-socket fetch-test-server http://localhost/host)
+    [[ "$data" == "localhost /host" ]]
+
+    run $FETCH --unix-socket ${TMPDIR:-/tmp}/no-such-socket.sock http://localhost/
+    [[ "$status" != "0" ]]
+}
+
+@test "select HTTP version (--http1.1, --http2, --http2-prior-knowledge)" {
+    data=$($FETCH -k --http1.1 https://localhost:9000/version)
+    [[ "$data" == "HTTP/1.1" ]]
+
+    data=$($FETCH -k --http2 https://localhost:9000/version)
+    [[ "$data" == "HTTP/2.0" ]]
+
+    data=$($FETCH -k --http2 -i https://localhost:9000/)
+    [[ "$data" == "HTTP/2 200"* ]]
+
+    # h2c with prior knowledge
+    data=$($FETCH --http2-prior-knowledge http://localhost:9002/version)
+    [[ "$data" == "HTTP/2.0" ]]
+
+    data=$($FETCH http://localhost:9002/version)
+    [[ "$data" == "HTTP/1.1" ]]
+}
+
+@test "select HTTP/3 (--http3, --http3-only)" {
+    run $FETCH -k --http3-only https://localhost:9000/version
+    if [[ "$output" == *"not supported by this build"* ]] ; then
+        skip "fetch is built without the cargo feature http3"
+    fi
+    [[ "$output" == "HTTP/3.0" ]]
+
+    data=$($FETCH -k --http3 https://localhost:9000/version)
+    [[ "$data" == "HTTP/3.0" ]]
+
+    data=$($FETCH -k --http3 -i https://localhost:9000/)
+    [[ "$data" == "HTTP/3 200"* ]]
+
+    data=$($FETCH -k --http3-only -w '%{certs}' https://localhost:9000/)
+    [[ "$data" == "Welcome!TLS session: TLSv1.3, cipher TLS_AES_"*", ALPN h3"* ]]
+
+    data=$($FETCH -k --http3-only -d "Hello, HTTP/3!" https://localhost:9000/echo_post)
+    [[ "$data" == "Hello, HTTP/3!" ]]
+
+    # HTTP/3 requires https
+    data=$($FETCH --http3 http://localhost:9002/version)
+    [[ "$data" == "HTTP/1.1" ]]
+
+    run $FETCH --http3-only http://localhost:9002/version
+    [[ "$status" == "1" ]]
+}
+
+@test "select TLS backend (--tls-backend)" {
+    data=$($FETCH -k -v --tls-backend rustls https://localhost:9000/ 2>&1)
+    [[ "$data" == *"Using TLS backend rustls"* ]]
+    [[ "$data" == *"Welcome!"* ]]
+
+    run $FETCH -k --tls-backend native --tlsv1.3 https://localhost:9000/
+    [[ "$status" == "1" ]]
+}
+
+@test "limit TLS version (--tls-max)" {
+    data=$($FETCH -k --tls-max 1.2 https://localhost:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    run $FETCH -k --tlsv1.3 --tls-max 1.2 https://localhost:9000/
+    [[ "$status" == "1" ]]
+}
+
+@test "select cipher suites and curves (--ciphers, --tls13-ciphers, --curves)" {
+    data=$($FETCH -k --tls-max 1.2 --ciphers ECDHE-RSA-AES128-GCM-SHA256 https://localhost:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    # the server uses a RSA certificate
+    run $FETCH -k --tls-max 1.2 --ciphers ECDHE-ECDSA-AES128-GCM-SHA256 https://localhost:9000/
+    [[ "$status" != "0" ]]
+
+    data=$($FETCH -k --tls13-ciphers TLS_CHACHA20_POLY1305_SHA256 --curves P-384 https://localhost:9000/)
+    [[ "$data" == "Welcome!" ]]
+
+    run $FETCH -k --curves brainpool https://localhost:9000/
+    [[ "$status" == "1" ]]
+}
+
+@test "require stapled OCSP response (--cert-status)" {
+    data=$($FETCH -k --cert-status https://localhost:9003/)
+    [[ "$data" == "Welcome!" ]]
+
+    # the certificate is not checked without --cert-status
+    data=$($FETCH -k https://localhost:9004/)
+    [[ "$data" == "Welcome!" ]]
+}
+
+@test "fail on revoked or missing OCSP response (exit code 91)" {
+    run $FETCH -k --cert-status https://localhost:9004/
+    [[ "$status" == "91" ]]
+    [[ "$output" == *"server certificate is revoked (OCSP)"* ]]
+
+    run $FETCH -k --cert-status https://localhost:9000/
+    [[ "$status" == "91" ]]
+    [[ "$output" == *"no OCSP response stapled by the server"* ]]
+
+    run $FETCH -k --cert-status --tls-backend native https://localhost:9003/
+    [[ "$status" == "1" ]]
+}
+
+@test "show certificates and TLS session (--show-certs, %{certs})" {
+    run $FETCH -k --show-certs --tls-max 1.2 --http1.1 https://localhost:9000/
+    [[ "$status" == "0" ]]
+    [[ "$output" == *"TLS session: TLSv1.2, cipher TLS_ECDHE_RSA_WITH_"*", ALPN http/1.1"* ]]
+    [[ "$output" == *"subject: C=DE; ST=Some-State; O=Internet Widgits Pty Ltd; CN=localhost"* ]]
+    [[ "$output" == *"SHA-256 fingerprint: DE:F3:8D:EC"* ]]
+
+    data=$($FETCH -k -w '%{num_certs} %{certs}' https://localhost:9000/)
+    [[ "$data" == "Welcome!1 TLS session: TLSv1.3, cipher TLS_AES_"*", ALPN h2"* ]]
+
+    # the certificates are shown if the verification fails
+    run $FETCH --show-certs https://localhost:9000/
+    [[ "$status" != "0" ]]
+    [[ "$output" == *"Certificate 0:"* ]]
+}
+
+@test "log TLS keys (SSLKEYLOGFILE)" {
+    SSLKEYLOGFILE="$BATS_TEST_TMPDIR/keys.log" $FETCH -k --tls-backend rustls https://localhost:9000/
+    grep -q "^CLIENT_HANDSHAKE_TRAFFIC_SECRET " "$BATS_TEST_TMPDIR/keys.log"
+
+    # TLS 1.2 and client certificates
+    data=$(SSLKEYLOGFILE="$BATS_TEST_TMPDIR/keys12.log" $FETCH -k --tls-backend rustls --tls-max 1.2 -E test/certs/client.pem --key test/certs/client.key https://localhost:9001/)
+    [[ "$data" == "Welcome, client!" ]]
+    grep -q "^CLIENT_RANDOM " "$BATS_TEST_TMPDIR/keys12.log"
+
+    # the native TLS backend does not log the keys
+    run env SSLKEYLOGFILE="$BATS_TEST_TMPDIR/native.log" $FETCH -k --tls-backend native https://localhost:9000/
+    [[ "$output" == *"SSLKEYLOGFILE is ignored by the native TLS backend"* ]]
+    [[ ! -e "$BATS_TEST_TMPDIR/native.log" ]]
+}